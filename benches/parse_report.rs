@@ -0,0 +1,182 @@
+use calamine::{open_workbook, Reader, Xlsx};
+use chrono::{Datelike, Duration, NaiveDate};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use money_manager::{
+    parse_report, read_worksheet_from_range, ColumnHeaders, GroupBy, ParseOptions, Period,
+    TxTypeLabels,
+};
+use std::path::Path;
+use xlsxwriter::Workbook;
+
+const SHEETS: usize = 16;
+const ROWS_PER_SHEET: u32 = 5_000;
+const CATEGORIES: &[&str] = &["Еда", "Транспорт", "Жильё", "Связь", "Здоровье", "Отдых"];
+
+fn by_month(date: NaiveDate) -> Period {
+    Period::from(format!("{:04}-{:02}", date.year(), date.month()))
+}
+
+fn transaction_date(row: u32) -> NaiveDate {
+    NaiveDate::from_ymd(2020, 1, 1) + Duration::days(i64::from(row % 730))
+}
+
+fn write_workbook(path: &Path, sheets: usize, rows: u32) {
+    let headers = ColumnHeaders::default();
+    let labels = TxTypeLabels::default();
+
+    let workbook = Workbook::new(path.to_str().unwrap());
+    for sheet in 0..sheets {
+        let mut worksheet = workbook
+            .add_worksheet(Some(&format!("Счёт {}", sheet + 1)))
+            .unwrap();
+        let columns = [
+            &headers.period,
+            &headers.category,
+            &headers.tx_type,
+            &headers.value,
+        ];
+        for (col, name) in columns.iter().enumerate() {
+            worksheet.write_string(0, col as u16, name, None).unwrap();
+        }
+        for row in 1..=rows {
+            let date = transaction_date(row);
+            let category = CATEGORIES[row as usize % CATEGORIES.len()];
+            worksheet
+                .write_string(row, 0, &date.format("%d.%m.%Y").to_string(), None)
+                .unwrap();
+            worksheet.write_string(row, 1, category, None).unwrap();
+            worksheet
+                .write_string(row, 2, &labels.outcome, None)
+                .unwrap();
+            worksheet
+                .write_number(row, 3, f64::from(row % 997), None)
+                .unwrap();
+        }
+    }
+    workbook.close().unwrap();
+}
+
+fn parse_sequential(path: &Path) -> usize {
+    let mut workbook: Xlsx<_> = open_workbook(path).unwrap();
+    workbook
+        .worksheets()
+        .into_iter()
+        .map(|(name, range)| {
+            read_worksheet_from_range(name, range, by_month, ColumnHeaders::default())
+                .unwrap()
+                .len()
+        })
+        .sum()
+}
+
+fn bench_parse_report(c: &mut Criterion) {
+    let path = std::env::temp_dir().join("money_manager_bench_sheets.xlsx");
+    write_workbook(&path, SHEETS, ROWS_PER_SHEET);
+    let file = path.to_str().unwrap().to_string();
+    let parse_report_id = if cfg!(feature = "parallel") {
+        "parse_report (parallel)"
+    } else {
+        "parse_report (sequential)"
+    };
+
+    let mut group = c.benchmark_group("multi_sheet_workbook");
+    group.sample_size(10);
+    group.bench_function("sequential", |b| {
+        b.iter(|| parse_sequential(black_box(&path)))
+    });
+    group.bench_function(parse_report_id, |b| {
+        b.iter(|| {
+            parse_report(
+                black_box(file.clone()),
+                GroupBy::Month,
+                &ParseOptions::default(),
+            )
+            .unwrap()
+        })
+    });
+    group.finish();
+}
+
+#[cfg(feature = "parallel")]
+mod parallel {
+    use super::*;
+    use calamine::{DataType, Range};
+    use criterion::BatchSize;
+    use money_manager::read_worksheet_parallel;
+
+    fn synthetic_worksheets(sheets: usize, rows: u32) -> Vec<(String, Range<DataType>)> {
+        let headers = ColumnHeaders::default();
+        let labels = TxTypeLabels::default();
+        let string = |s: &str| DataType::String(s.to_string());
+
+        (0..sheets)
+            .map(|sheet| {
+                let mut range = Range::new((0, 0), (rows, 3));
+                let columns = [
+                    &headers.period,
+                    &headers.category,
+                    &headers.tx_type,
+                    &headers.value,
+                ];
+                for (col, name) in columns.iter().enumerate() {
+                    range.set_value((0, col as u32), string(name));
+                }
+                for row in 1..=rows {
+                    let date = transaction_date(row).format("%d.%m.%Y").to_string();
+                    range.set_value((row, 0), string(&date));
+                    range.set_value(
+                        (row, 1),
+                        string(CATEGORIES[row as usize % CATEGORIES.len()]),
+                    );
+                    range.set_value((row, 2), string(&labels.outcome));
+                    range.set_value((row, 3), DataType::Float(f64::from(row % 997)));
+                }
+                (format!("Счёт {}", sheet + 1), range)
+            })
+            .collect()
+    }
+
+    pub fn bench_read_worksheet_parallel(c: &mut Criterion) {
+        let worksheets = synthetic_worksheets(SHEETS, ROWS_PER_SHEET);
+
+        let mut group = c.benchmark_group("preloaded_worksheets");
+        group.sample_size(10);
+        group.bench_function("sequential", |b| {
+            b.iter_batched(
+                || worksheets.clone(),
+                |worksheets| {
+                    worksheets
+                        .into_iter()
+                        .map(|(name, range)| {
+                            read_worksheet_from_range(
+                                name,
+                                range,
+                                by_month,
+                                ColumnHeaders::default(),
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                },
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function("read_worksheet_parallel", |b| {
+            b.iter_batched(
+                || worksheets.clone(),
+                |worksheets| read_worksheet_parallel(worksheets, by_month),
+                BatchSize::LargeInput,
+            )
+        });
+        group.finish();
+    }
+}
+
+#[cfg(feature = "parallel")]
+criterion_group!(
+    benches,
+    bench_parse_report,
+    parallel::bench_read_worksheet_parallel
+);
+#[cfg(not(feature = "parallel"))]
+criterion_group!(benches, bench_parse_report);
+criterion_main!(benches);
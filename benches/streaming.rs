@@ -0,0 +1,78 @@
+use calamine::{open_workbook, Reader, Xlsx};
+use chrono::{Datelike, Duration, NaiveDate};
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use money_manager::{
+    read_worksheet_from_range, read_worksheet_streaming, ColumnHeaders, Period, TxTypeLabels,
+};
+use std::path::Path;
+use xlsxwriter::Workbook;
+
+const SHEET: &str = "Счёт";
+const ROWS: u32 = 100_000;
+const CATEGORIES: &[&str] = &["Еда", "Транспорт", "Жильё", "Связь", "Здоровье", "Отдых"];
+
+fn by_month(date: NaiveDate) -> Period {
+    Period::from(format!("{:04}-{:02}", date.year(), date.month()))
+}
+
+fn write_workbook(path: &Path, rows: u32) {
+    let headers = ColumnHeaders::default();
+    let labels = TxTypeLabels::default();
+
+    let workbook = Workbook::new(path.to_str().unwrap());
+    let mut worksheet = workbook.add_worksheet(Some(SHEET)).unwrap();
+    let columns = [
+        &headers.period,
+        &headers.category,
+        &headers.tx_type,
+        &headers.value,
+    ];
+    for (col, name) in columns.iter().enumerate() {
+        worksheet.write_string(0, col as u16, name, None).unwrap();
+    }
+    for row in 1..=rows {
+        let date = NaiveDate::from_ymd(2015, 1, 1) + Duration::days(i64::from(row % 2_500));
+        let category = CATEGORIES[row as usize % CATEGORIES.len()];
+        worksheet
+            .write_string(row, 0, &date.format("%d.%m.%Y").to_string(), None)
+            .unwrap();
+        worksheet.write_string(row, 1, category, None).unwrap();
+        worksheet
+            .write_string(row, 2, &labels.outcome, None)
+            .unwrap();
+        worksheet
+            .write_number(row, 3, f64::from(row % 997), None)
+            .unwrap();
+    }
+    workbook.close().unwrap();
+}
+
+fn read_whole_range(path: &Path) -> usize {
+    let mut workbook: Xlsx<_> = open_workbook(path).unwrap();
+    let range = workbook.worksheet_range(SHEET).unwrap().unwrap();
+    read_worksheet_from_range(SHEET.to_string(), range, by_month, ColumnHeaders::default())
+        .unwrap()
+        .len()
+}
+
+fn bench_streaming(c: &mut Criterion) {
+    let path = std::env::temp_dir().join("money_manager_bench_streaming.xlsx");
+    write_workbook(&path, ROWS);
+
+    let mut group = c.benchmark_group("large_worksheet");
+    group.sample_size(10);
+    group.bench_function("worksheet_range", |b| {
+        b.iter(|| read_whole_range(black_box(&path)))
+    });
+    group.bench_function("read_worksheet_streaming", |b| {
+        b.iter(|| {
+            read_worksheet_streaming(black_box(&path), SHEET, by_month)
+                .unwrap()
+                .len()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_streaming);
+criterion_main!(benches);
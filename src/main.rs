@@ -1,34 +1,1393 @@
+use chrono::{Local, NaiveDate};
 use clap::Clap;
-use money_manager::{GroupBy, parse_report, draw, MyCustomError};
+use notify::event::ModifyKind;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use comfy_table::{Attribute, Cell, Table};
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::fs::File;
+use std::io::BufReader;
+use std::cmp::Ordering;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::time::Duration;
+use tracing::{error, info, info_span, Level};
+use money_manager::{
+    GroupBy, parse_report, draw, read_config, apply_aliases, Config, MyCustomError,
+    worksheet_data_to_periods, last_n_groups, infer_date_range_from_data, remove_empty_periods,
+    merge_worksheet_data, ParseOptions, DrawConfig, Palette,
+    normalize_to_percent, export_csv, export_csv_multi, detect_recurring_categories,
+    category_stats, export_sqlite_with_mode, WorksheetData, Period, MAX_PERIODS,
+    RECURRING_THRESHOLD, trim_category_names, lowercase_category_names, MissingPeriodStrategy,
+    SortOrder, CurrencyFormat, CurrencyPosition, format_currency, compare_periods,
+    last_n_groups_before, ChartType, detect_anomalies, ANOMALY_SIGMA, list_sheets, TxTypeLabels,
+    generate_config, read_currency_map, CurrencyConversion, read_budget_file,
+    compute_monthly_budget_variance, Category, ViewMode, pivot_to_category_share,
+    top_spending_months, bottom_spending_months, diff_worksheet_data,
+    export_html_table, OutputFormat, compute_period_over_period_delta,
+    compute_period_over_period_abs_delta, worksheet_data_summary, export_xlsx,
+    read_schema, validate_against_schema, category_correlation,
+    hierarchical_categories, flatten_hierarchy, impute_missing_categories,
+    import_ndjson, compute_spending_velocity, budgets_per_period,
+    merge_small_categories, seasonality_index,
+    read_period_groups, aggregate_by_custom_periods, repair_worksheet_data,
+    infer_group_by_from_data, GroupByParseError, generate_sample_xlsx, Smoothing,
+    scale_worksheet_data, validate_period_continuity, ColorMode, read_cpi_file,
+    apply_inflation_adjustment, diff_category_totals, filter_by_value_range,
+    compute_zscore_table, group_categories_by_tag, tag_totals, ReportConfig,
+    generate_full_report, read_workbook_metadata, compute_budget_adherence_score, budget_grade,
+    parse_report_with_inferred_grouping,
+};
+
+#[cfg(feature = "tui")]
+mod tui;
+
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(500);
 
 #[derive(Clap, Debug)]
-#[clap(name = "money_manager")]
+#[clap(
+    name = "money_manager",
+    after_help = "Options that take a value can also be set with MONEY_MANAGER_<OPTION> \
+                  environment variables, e.g. MONEY_MANAGER_FILE for --file. Command line \
+                  flags take precedence. See README.md for the full list."
+)]
 struct Args {
-    #[clap(short, long)]
-    file: String,
+    #[clap(short, long, env = "MONEY_MANAGER_FILE")]
+    file: Option<String>,
+
+    #[clap(short, long, parse(try_from_str), env = "MONEY_MANAGER_GROUP_BY")]
+    group_by: Option<GroupByArg>,
+
+    #[clap(short, long, env = "MONEY_MANAGER_CONFIG")]
+    config: Option<String>,
+
+    #[clap(long)]
+    generate_config: bool,
+
+    #[clap(long)]
+    watch: bool,
+
+    #[cfg(feature = "tui")]
+    #[clap(long)]
+    interactive_tui: bool,
+
+    #[clap(long, env = "MONEY_MANAGER_OUTPUT_DIR")]
+    output_dir: Option<String>,
+
+    #[clap(long, default_value = "info", env = "MONEY_MANAGER_LOG_LEVEL")]
+    log_level: Level,
+
+    #[clap(short, long, env = "MONEY_MANAGER_PERIODS")]
+    periods: Option<usize>,
+
+    #[clap(long)]
+    since_last: bool,
+
+    #[clap(long, parse(from_str), conflicts_with = "since-last", env = "MONEY_MANAGER_ANCHOR_PERIOD")]
+    anchor_period: Option<Period>,
+
+    #[clap(long, conflicts_with = "include-zero-periods")]
+    exclude_zero_periods: bool,
+
+    #[clap(long)]
+    include_zero_periods: bool,
+
+    #[clap(long)]
+    aggregate_sheets: bool,
+
+    #[clap(long)]
+    include_transfers: bool,
+
+    #[clap(long, env = "MONEY_MANAGER_INCOME_LABEL")]
+    income_label: Option<String>,
+
+    #[clap(long, env = "MONEY_MANAGER_OUTCOME_LABEL")]
+    outcome_label: Option<String>,
+
+    #[clap(long, use_delimiter = true, env = "MONEY_MANAGER_EXTRA_INCOME_LABELS")]
+    extra_income_labels: Vec<String>,
+
+    #[clap(long, env = "MONEY_MANAGER_SHEET_NAME_REGEX")]
+    sheet_name_regex: Option<String>,
+
+    #[clap(long, env = "MONEY_MANAGER_SHEET_ALIAS")]
+    sheet_alias: Vec<String>,
+
+    #[clap(long)]
+    trim_whitespace: bool,
+
+    #[clap(long)]
+    case_insensitive_categories: bool,
+
+    #[clap(long, default_value = "1400", env = "MONEY_MANAGER_CHART_WIDTH")]
+    chart_width: u32,
+
+    #[clap(long, default_value = "740", env = "MONEY_MANAGER_CHART_HEIGHT")]
+    chart_height: u32,
+
+    #[clap(long, env = "MONEY_MANAGER_PALETTE")]
+    palette: Option<Palette>,
+
+    #[clap(long, default_value = "light", env = "MONEY_MANAGER_COLOR_MODE")]
+    color_mode: ColorMode,
+
+    #[clap(long)]
+    percent_of_total: bool,
+
+    #[clap(long)]
+    summary: bool,
+
+    #[clap(long, default_value = "absolute", env = "MONEY_MANAGER_VIEW_MODE")]
+    view_mode: ViewMode,
+
+    #[clap(long, default_value = "/", env = "MONEY_MANAGER_HIERARCHY_SEPARATOR")]
+    hierarchy_separator: char,
+
+    #[clap(long, env = "MONEY_MANAGER_EXPORT_CSV")]
+    export_csv: Option<String>,
+
+    #[clap(long, env = "MONEY_MANAGER_EXPORT_SQLITE")]
+    export_sqlite: Option<String>,
+
+    #[clap(long, env = "MONEY_MANAGER_EXPORT_HTML_TABLE")]
+    export_html_table: Option<String>,
+
+    #[clap(long, env = "MONEY_MANAGER_EXPORT_XLSX")]
+    export_xlsx: Option<String>,
+
+    #[clap(long, default_value = "browser", env = "MONEY_MANAGER_OUTPUT_FORMAT")]
+    output_format: OutputFormat,
+
+    #[clap(long, env = "MONEY_MANAGER_OUTPUT_FILE")]
+    output_file: Option<String>,
+
+    #[clap(long, default_value = "spending_amount", env = "MONEY_MANAGER_METRIC_NAME")]
+    metric_name: String,
+
+    #[clap(long, conflicts_with = "overwrite")]
+    append: bool,
+
+    #[clap(long)]
+    overwrite: bool,
+
+    #[clap(long)]
+    recurring_only: bool,
+
+    #[clap(long)]
+    show_savings_rate: bool,
+
+    #[clap(long)]
+    show_net_worth: bool,
+
+    #[clap(long, default_value = "0", env = "MONEY_MANAGER_INITIAL_BALANCE")]
+    initial_balance: f64,
+
+    #[clap(long)]
+    show_expense_ratio: bool,
+
+    #[clap(long, env = "MONEY_MANAGER_INCOME_CATEGORY")]
+    income_category: Option<String>,
+
+    #[clap(long, env = "MONEY_MANAGER_EXPENSE_RATIO_TARGET")]
+    expense_ratio_target: Option<f64>,
+
+    #[clap(long, default_value = "zero", env = "MONEY_MANAGER_MISSING_PERIOD_STRATEGY")]
+    missing_period_strategy: MissingPeriodStrategy,
+
+    #[clap(long, default_value = "none", env = "MONEY_MANAGER_SMOOTHING")]
+    smoothing: Smoothing,
+
+    #[clap(long, default_value = "3", env = "MONEY_MANAGER_SMOOTHING_WINDOW")]
+    smoothing_window: usize,
+
+    #[clap(long, default_value = "alpha", env = "MONEY_MANAGER_SORT_CATEGORIES")]
+    sort_categories: SortOrder,
+
+    #[clap(long, default_value = "₽", env = "MONEY_MANAGER_CURRENCY")]
+    currency: String,
+
+    #[clap(long, default_value = "suffix", env = "MONEY_MANAGER_CURRENCY_POSITION")]
+    currency_position: CurrencyPosition,
+
+    #[clap(long, env = "MONEY_MANAGER_CURRENCY_MAP")]
+    currency_map: Option<String>,
+
+    #[clap(long, env = "MONEY_MANAGER_BUDGET_FILE")]
+    budget_file: Option<String>,
+
+    #[clap(long, env = "MONEY_MANAGER_CPI_FILE")]
+    cpi_file: Option<String>,
+
+    #[clap(long, parse(from_str), env = "MONEY_MANAGER_BASE_PERIOD")]
+    base_period: Option<Period>,
+
+    #[clap(long, default_value = "0", env = "MONEY_MANAGER_SKIP_ROWS")]
+    skip_rows: usize,
+
+    #[clap(long, conflicts_with = "skip-rows", env = "MONEY_MANAGER_HEADER_ROW")]
+    header_row: Option<usize>,
+
+    #[clap(long)]
+    strict_headers: bool,
+
+    #[clap(long, env = "MONEY_MANAGER_VALUE_COLUMN_CURRENCY")]
+    value_column_currency: Option<String>,
+
+    #[clap(long, conflicts_with = "value-column-currency", env = "MONEY_MANAGER_VALUE_COLUMN_NAME")]
+    value_column_name: Option<String>,
+
+    #[clap(long)]
+    warn_duplicates: bool,
+
+    #[clap(long)]
+    deduplicate: bool,
+
+    #[clap(long)]
+    strict_duplicates: bool,
+
+    #[clap(long)]
+    lenient: bool,
+
+    #[clap(long, conflicts_with = "canonical-categories")]
+    impute_all_categories: bool,
+
+    #[clap(long, env = "MONEY_MANAGER_CANONICAL_CATEGORIES")]
+    canonical_categories: Option<String>,
+
+    #[clap(long, env = "MONEY_MANAGER_MERGE_BELOW_PCT")]
+    merge_below_pct: Option<f64>,
+
+    #[clap(long, env = "MONEY_MANAGER_PERIOD_GROUPS_FILE")]
+    period_groups_file: Option<String>,
+
+    #[clap(long)]
+    include_ungrouped: bool,
+
+    #[clap(long)]
+    warn_gaps: bool,
+
+    #[clap(long)]
+    fill_gaps: bool,
+
+    #[clap(long)]
+    auto_sign_fix: bool,
+
+    #[clap(long, conflicts_with = "value-divisor", env = "MONEY_MANAGER_VALUE_MULTIPLIER")]
+    value_multiplier: Option<f64>,
+
+    #[clap(long, env = "MONEY_MANAGER_VALUE_DIVISOR")]
+    value_divisor: Option<f64>,
+
+    #[clap(long, env = "MONEY_MANAGER_MIN_VALUE")]
+    min_value: Option<f64>,
+
+    #[clap(long, env = "MONEY_MANAGER_MAX_VALUE")]
+    max_value: Option<f64>,
+
+    #[clap(long, default_value = "RUB", env = "MONEY_MANAGER_TARGET_CURRENCY")]
+    target_currency: String,
+
+    #[clap(long, default_value = "1000", env = "MONEY_MANAGER_CURRENCY_DIVISOR")]
+    currency_divisor: f64,
+
+    #[clap(long, default_value = "0", env = "MONEY_MANAGER_THRESHOLD")]
+    threshold: f64,
+
+    #[clap(long, env = "MONEY_MANAGER_CHART_TYPE")]
+    chart_type: Option<ChartType>,
+
+    #[clap(long)]
+    annotate_anomalies: bool,
+
+    #[clap(long, env = "MONEY_MANAGER_ANOMALY_SIGMA")]
+    anomaly_sigma: Option<f64>,
+
+    #[clap(long, env = "MONEY_MANAGER_PERIOD_LABEL_FORMAT")]
+    period_label_format: Option<String>,
+
+    #[clap(long, env = "MONEY_MANAGER_CHART_TITLE")]
+    chart_title: Option<String>,
+
+    #[clap(long)]
+    show_year_boundaries: bool,
+
+    #[clap(long)]
+    show_forecast: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Clap, Debug)]
+enum Command {
+    Stats(Stats),
+    Compare(Compare),
+    Diff(Diff),
+    Check(Check),
+    ListSheets,
+    GenerateSample(GenerateSample),
+    #[clap(alias = "budget-report")]
+    Report(Report),
+    Inspect,
+}
+
+#[derive(Debug)]
+enum StatsSortBy {
+    Category,
+    Mean,
+    Growth,
+}
+
+impl FromStr for StatsSortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "category" => Ok(StatsSortBy::Category),
+            "mean" => Ok(StatsSortBy::Mean),
+            "growth" => Ok(StatsSortBy::Growth),
+            _ => Err(format!("Unknown sort key '{}'", s)),
+        }
+    }
+}
+
+#[derive(Clap, Debug)]
+struct Stats {
+    #[clap(long)]
+    show_anomalies: bool,
+
+    #[clap(long)]
+    show_budget_variance: bool,
+
+    #[clap(long)]
+    top_periods: Option<usize>,
+
+    #[clap(long)]
+    delta: bool,
+
+    #[clap(long)]
+    correlation_matrix: bool,
+
+    #[clap(long)]
+    per_day_rate: bool,
+
+    #[clap(long)]
+    seasonality: bool,
+
+    #[clap(long)]
+    show_box_stats: bool,
+
+    #[clap(long, default_value = "category")]
+    sort_by: StatsSortBy,
+}
+
+#[derive(Clap, Debug)]
+struct Compare {
+    #[clap(parse(from_str))]
+    period1: Period,
+
+    #[clap(parse(from_str))]
+    period2: Period,
+}
+
+#[derive(Clap, Debug)]
+struct Check {
+    #[clap(long)]
+    schema: String,
+}
+
+#[derive(Clap, Debug)]
+struct Report {
+    #[clap(long)]
+    output: String,
+
+    #[clap(long, default_value = "5")]
+    top: usize,
+}
+
+#[derive(Clap, Debug)]
+struct GenerateSample {
+    #[clap(long)]
+    output: String,
+
+    #[clap(long, default_value = "12")]
+    months: u32,
+
+    #[clap(long, use_delimiter = true, default_value = "Продукты,Транспорт,Кафе,Связь")]
+    categories: Vec<String>,
+}
+
+#[derive(Clap, Debug)]
+struct Diff {
+    #[clap(long, alias = "before")]
+    file_before: String,
+
+    #[clap(long, alias = "after")]
+    file_after: String,
+
+    #[clap(long)]
+    table: bool,
+}
+
+#[derive(Debug, Clone)]
+enum GroupByArg {
+    Auto,
+    Fixed(GroupBy),
+}
+
+impl FromStr for GroupByArg {
+    type Err = GroupByParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(GroupByArg::Auto),
+            _ => s.parse().map(GroupByArg::Fixed),
+        }
+    }
+}
+
+fn merge_config(mut args: Args, config: &Config) -> Result<Args, MyCustomError> {
+    let invalid = MyCustomError::InvalidConfig;
+
+    args.file = args.file.or_else(|| config.file.clone());
+    if args.group_by.is_none() {
+        if let Some(group_by) = &config.group_by {
+            args.group_by = Some(group_by.parse::<GroupByArg>()?);
+        }
+    }
+    args.periods = args.periods.or(config.max_periods);
+    args.output_dir = args.output_dir.or_else(|| config.output_dir.clone());
+    args.sheet_name_regex = args.sheet_name_regex.or_else(|| config.sheet_filter.clone());
+    if args.chart_type.is_none() {
+        if let Some(chart_type) = &config.chart_type {
+            args.chart_type = Some(chart_type.parse().map_err(invalid)?);
+        }
+    }
+    if args.palette.is_none() {
+        if let Some(palette) = &config.palette {
+            args.palette = Some(palette.parse().map_err(invalid)?);
+        }
+    }
+    if let Some(labels) = &config.tx_type_labels {
+        args.income_label = args.income_label.or_else(|| Some(labels.income.clone()));
+        args.outcome_label = args.outcome_label.or_else(|| Some(labels.outcome.clone()));
+    }
+
+    Ok(args)
+}
+
+fn input_file(args: &Args) -> Result<&str, MyCustomError> {
+    args.file
+        .as_deref()
+        .ok_or_else(|| MyCustomError::InvalidConfig(String::from("no input file given")))
+}
+
+fn group_by(args: &Args, periods: &[Period]) -> GroupBy {
+    match &args.group_by {
+        Some(GroupByArg::Fixed(group_by)) => group_by.clone(),
+        Some(GroupByArg::Auto) => infer_group_by_from_data(periods),
+        None => GroupBy::Month,
+    }
+}
+
+fn output_path(args: &Args, path: &str) -> PathBuf {
+    match &args.output_dir {
+        Some(dir) => Path::new(dir).join(path),
+        None => PathBuf::from(path),
+    }
+}
+
+fn sheet_filter(args: &Args) -> Result<Option<Regex>, MyCustomError> {
+    match &args.sheet_name_regex {
+        Some(regex) => {
+            Ok(Some(Regex::new(regex).map_err(|e| MyCustomError::InvalidConfig(e.to_string()))?))
+        }
+        None => Ok(None),
+    }
+}
+
+fn sheet_names(args: &Args) -> Result<Vec<String>, MyCustomError> {
+    let file = match &args.file {
+        Some(file) if !args.aggregate_sheets && !file.ends_with(".ndjson") => file,
+        _ => return Ok(Vec::new()),
+    };
+    let filter = sheet_filter(args)?;
+    Ok(list_sheets(file)?
+        .into_iter()
+        .filter(|name| filter.as_ref().map_or(true, |filter| filter.is_match(name)))
+        .collect())
+}
+
+fn sheet_aliases(args: &Args) -> Result<HashMap<String, String>, MyCustomError> {
+    args.sheet_alias
+        .iter()
+        .map(|alias| match alias.split_once('=') {
+            Some((name, alias)) => Ok((name.to_string(), alias.to_string())),
+            None => Err(MyCustomError::InvalidConfig(format!(
+                "sheet alias '{}' must look like NAME=ALIAS",
+                alias
+            ))),
+        })
+        .collect()
+}
 
-    #[clap(short, long, default_value = "month")]
-    group_by: String,
+fn inflation_base(args: &Args, periods: &[Period]) -> Period {
+    args.base_period
+        .clone()
+        .or_else(|| periods.iter().max().cloned())
+        .unwrap_or_default()
 }
 
-fn group_by(group: String) -> GroupBy {
-    match String::from(group).as_str() {
-        "year" => GroupBy::Year,
-        "quarter" => GroupBy::Quarter,
-        _ => GroupBy::Month,
+fn value_scale(args: &Args) -> Result<Option<f64>, MyCustomError> {
+    let factor = match (args.value_multiplier, args.value_divisor) {
+        (Some(multiplier), _) => multiplier,
+        (None, Some(divisor)) if divisor > 0.0 => 1.0 / divisor,
+        (None, Some(divisor)) => {
+            return Err(MyCustomError::InvalidConfig(format!(
+                "value divisor must be positive, got {}",
+                divisor
+            )))
+        }
+        (None, None) => return Ok(None),
+    };
+    if factor <= 0.0 {
+        return Err(MyCustomError::InvalidConfig(format!(
+            "value multiplier must be positive, got {}",
+            factor
+        )));
     }
+    Ok(Some(factor))
+}
+
+fn load_data(
+    args: &Args,
+    config: &Config,
+) -> Result<(Vec<WorksheetData>, Vec<Period>), MyCustomError> {
+    load_file(args, config, input_file(args)?)
 }
 
-fn draw_images(file: String, group: String) -> Result<String, MyCustomError> {
-    let data = parse_report(file, group_by(group))?;
+fn load_file(
+    args: &Args,
+    config: &Config,
+    file: &str,
+) -> Result<(Vec<WorksheetData>, Vec<Period>), MyCustomError> {
+    let n = args.periods.unwrap_or(MAX_PERIODS);
+    let sheet_filter = sheet_filter(args)?;
+    let currency_conversion = match &args.currency_map {
+        Some(path) => Some(CurrencyConversion {
+            rates: read_currency_map(path)?,
+            target: args.target_currency.clone(),
+        }),
+        None => None,
+    };
+    let default_labels = config.tx_type_labels.clone().unwrap_or_default();
+    let mut extra_income = default_labels.extra_income;
+    extra_income.extend(args.extra_income_labels.iter().cloned());
+    let mut column_headers = config.column_headers.clone().unwrap_or_default();
+    if let Some(value) = args.value_column_name.as_ref().or(args.value_column_currency.as_ref()) {
+        column_headers.value = value.clone();
+    }
+    let options = ParseOptions {
+        column_headers,
+        tx_type_labels: TxTypeLabels {
+            income: args.income_label.clone().unwrap_or(default_labels.income),
+            outcome: args.outcome_label.clone().unwrap_or(default_labels.outcome),
+            transfer: default_labels.transfer,
+            extra_income,
+        },
+        include_transfers: args.include_transfers,
+        sheet_filter,
+        currency_conversion,
+        skip_rows: args.header_row.unwrap_or(args.skip_rows),
+        strict_headers: args.strict_headers,
+        warn_duplicates: args.warn_duplicates,
+        deduplicate: args.deduplicate,
+        strict_duplicates: args.strict_duplicates,
+        lenient: args.lenient,
+    };
+
+    let (parsed, group) = if file.ends_with(".ndjson") {
+        let parsed = import_ndjson(BufReader::new(File::open(file)?))?;
+        let group = group_by(args, &worksheet_data_to_periods(&parsed));
+        (parsed, group)
+    } else if let Some(GroupByArg::Auto) = args.group_by {
+        parse_report_with_inferred_grouping(file.to_string(), &options)?
+    } else {
+        let group = group_by(args, &[]);
+        (parse_report(file.to_string(), group.clone(), &options)?, group)
+    };
+    let scale = value_scale(args)?;
+    let mut data: Vec<_> = parsed
+        .into_iter()
+        .map(|mut worksheet_data| {
+            if let Some(factor) = scale {
+                worksheet_data = scale_worksheet_data(worksheet_data, factor);
+            }
+            if args.min_value.is_some() || args.max_value.is_some() {
+                worksheet_data = filter_by_value_range(
+                    worksheet_data,
+                    args.min_value.unwrap_or(f64::NEG_INFINITY),
+                    args.max_value.unwrap_or(f64::INFINITY),
+                );
+            }
+            if args.trim_whitespace {
+                worksheet_data = trim_category_names(worksheet_data);
+            }
+            if args.case_insensitive_categories {
+                worksheet_data = lowercase_category_names(worksheet_data);
+            }
+            worksheet_data = apply_aliases(worksheet_data, &config.category_aliases);
+            if args.auto_sign_fix {
+                let (repaired, notes) = repair_worksheet_data(worksheet_data);
+                for note in notes {
+                    info!(
+                        category = %note.category,
+                        negative = note.negative_values,
+                        total = note.total_values,
+                        "flipped sign of income category"
+                    );
+                }
+                worksheet_data = repaired;
+            }
+            worksheet_data
+        })
+        .collect();
+    if args.aggregate_sheets {
+        data = vec![merge_worksheet_data(data)];
+    }
+
+    let all_periods = worksheet_data_to_periods(&data);
+    let mut periods: Vec<_> = if let Some(path) = &args.period_groups_file {
+        let mut groups = read_period_groups(path)?;
+        if args.include_ungrouped {
+            let grouped: BTreeSet<&Period> = groups.values().flatten().collect();
+            let ungrouped: Vec<Period> = all_periods
+                .iter()
+                .filter(|period| !grouped.contains(period))
+                .cloned()
+                .collect();
+            if !ungrouped.is_empty() {
+                groups.insert(String::from("Other"), ungrouped);
+            }
+        }
+        data = data
+            .iter()
+            .map(|worksheet_data| aggregate_by_custom_periods(worksheet_data, &groups))
+            .collect();
+        groups.keys().map(|name| Period::from(name.as_str())).collect()
+    } else if let Some(anchor) = &args.anchor_period {
+        last_n_groups_before(all_periods, n, anchor)
+    } else if args.since_last {
+        let (from, to) = infer_date_range_from_data(&all_periods, &group, n);
+        all_periods
+            .into_iter()
+            .filter(|p| *p >= from && *p <= to)
+            .collect()
+    } else {
+        last_n_groups(all_periods, n)
+    };
+
+    if args.warn_gaps || args.fill_gaps {
+        let gaps = validate_period_continuity(&periods, group.clone());
+        if args.warn_gaps {
+            for gap in &gaps {
+                eprintln!("warning: no data for period {} (after {})", gap.expected, gap.after);
+            }
+        }
+        if args.fill_gaps && !gaps.is_empty() {
+            let missing: Vec<Period> = gaps.into_iter().map(|gap| gap.expected).collect();
+            data = data
+                .into_iter()
+                .map(|worksheet_data| {
+                    let categories: Vec<Category> = worksheet_data.keys().cloned().collect();
+                    impute_missing_categories(worksheet_data, &categories, &missing)
+                })
+                .collect();
+            periods.extend(missing);
+            periods.sort();
+        }
+    }
+
+    if let Some(path) = &args.cpi_file {
+        let cpi_rates = read_cpi_file(path)?;
+        let base = inflation_base(args, &periods);
+        data = data
+            .into_iter()
+            .map(|worksheet_data| {
+                apply_inflation_adjustment(worksheet_data, &periods, &cpi_rates, &base)
+            })
+            .collect::<Result<_, _>>()?;
+    }
 
-    draw(data);
+    if args.exclude_zero_periods && !args.include_zero_periods {
+        let mut kept = BTreeSet::new();
+        for worksheet_data in data.iter_mut() {
+            let mut sheet_periods = periods.clone();
+            remove_empty_periods(worksheet_data, &mut sheet_periods);
+            kept.extend(sheet_periods);
+        }
+        periods.retain(|p| kept.contains(p));
+    }
+
+    if args.recurring_only {
+        for worksheet_data in data.iter_mut() {
+            let recurring = detect_recurring_categories(worksheet_data, &periods, RECURRING_THRESHOLD);
+            worksheet_data.retain(|cat, _| recurring.contains(cat));
+        }
+    }
+
+    if let Some(threshold) = args.merge_below_pct {
+        data = data
+            .into_iter()
+            .map(|worksheet_data| merge_small_categories(worksheet_data, &periods, threshold))
+            .collect();
+    }
+
+    let canonical: Option<Vec<Category>> = if let Some(path) = &args.canonical_categories {
+        let content = fs::read_to_string(path)?;
+        Some(
+            content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(Category::from)
+                .collect(),
+        )
+    } else if args.impute_all_categories {
+        let observed: BTreeSet<&Category> = data.iter().flat_map(|d| d.keys()).collect();
+        Some(observed.into_iter().cloned().collect())
+    } else {
+        None
+    };
+    if let Some(canonical) = canonical {
+        data = data
+            .into_iter()
+            .map(|worksheet_data| impute_missing_categories(worksheet_data, &canonical, &periods))
+            .collect();
+    }
+
+    Ok((data, periods))
+}
+
+fn budgets(args: &Args, periods: &[Period]) -> Result<BTreeMap<Category, f64>, MyCustomError> {
+    match &args.budget_file {
+        Some(path) => budgets_per_period(read_budget_file(path)?, &group_by(args, periods)),
+        None => Ok(BTreeMap::new()),
+    }
+}
+
+fn currency_format(args: &Args) -> CurrencyFormat {
+    CurrencyFormat {
+        symbol: args.currency.clone(),
+        position: args.currency_position,
+        divisor: args.currency_divisor,
+    }
+}
+
+fn draw_images(
+    args: &Args,
+    config: &Config,
+    mut data: Vec<WorksheetData>,
+    mut periods: Vec<Period>,
+) -> Result<String, MyCustomError> {
+    let mut draw_config = DrawConfig {
+        width: args.chart_width,
+        height: args.chart_height,
+        palette: args.palette.unwrap_or(Palette::Default),
+        show_savings_rate: args.show_savings_rate,
+        show_net_worth: args.show_net_worth,
+        initial_balance: args.initial_balance,
+        missing_period_strategy: args.missing_period_strategy,
+        sort_order: args.sort_categories,
+        currency: currency_format(args),
+        threshold: args.threshold,
+        chart_type: args.chart_type.unwrap_or(match args.command {
+            Some(Command::Diff(_)) => ChartType::Bar,
+            _ => ChartType::Line,
+        }),
+        annotate_anomalies: args.annotate_anomalies,
+        anomaly_sigma: args.anomaly_sigma.unwrap_or(ANOMALY_SIGMA),
+        group_by: group_by(args, &periods),
+        period_label_format: args.period_label_format.clone(),
+        show_forecast: args.show_forecast,
+        budgets: budgets(args, &periods)?,
+        output_format: args.output_format,
+        output_file: args.output_file.as_ref().map(|path| output_path(args, path)),
+        output_dir: args.output_dir.as_ref().map(PathBuf::from),
+        sheet_names: sheet_names(args)?,
+        sheet_aliases: sheet_aliases(args)?,
+        chart_title: args.chart_title.clone(),
+        smoothing: args.smoothing,
+        smoothing_window: args.smoothing_window,
+        show_year_boundaries: args.show_year_boundaries,
+        metric_name: args.metric_name.clone(),
+        show_expense_ratio: args.show_expense_ratio,
+        income_category: args.income_category.clone(),
+        expense_ratio_target: args.expense_ratio_target,
+        color_mode: args.color_mode,
+        inflation_base: args.cpi_file.as_ref().map(|_| inflation_base(args, &periods)),
+        ..DrawConfig::default()
+    };
+    draw_config.validate()?;
+
+    if args.summary {
+        let currency = currency_format(args);
+        let money = |amount: f64| format_currency(amount, &currency.symbol, currency.position);
+        for worksheet_data in &data {
+            let summary = worksheet_data_summary(worksheet_data, &periods);
+            println!(
+                "{} periods, {} categories. Income {}, expenses {}, net {}. \
+                 Top category: {}, most volatile: {}, Gini {:.2}.",
+                summary.total_periods,
+                summary.total_categories,
+                money(summary.total_income),
+                money(summary.total_expense),
+                money(summary.net),
+                summary.top_category,
+                summary.most_volatile_category,
+                summary.gini
+            );
+        }
+    }
+
+    if let Some(path) = &args.export_csv {
+        let mut file = File::create(output_path(args, path))?;
+        if data.len() == 1 {
+            export_csv(&data[0], &periods, &mut file)?;
+        } else {
+            export_csv_multi(&data, &periods, &mut file)?;
+        }
+    }
+
+    if let Some(path) = &args.export_html_table {
+        let mut file = File::create(output_path(args, path))?;
+        for worksheet_data in &data {
+            export_html_table(worksheet_data, &periods, &mut file)?;
+        }
+    }
+
+    if let Some(path) = &args.export_xlsx {
+        export_xlsx(&data, &periods, &output_path(args, path))?;
+    }
+
+    if let Some(path) = &args.export_sqlite {
+        let path = output_path(args, path);
+        if path.exists() && !args.append && !args.overwrite {
+            return Err(MyCustomError::InvalidConfig(format!(
+                "{} already exists, use --append or --overwrite",
+                path.display()
+            )));
+        }
+        export_sqlite_with_mode(&data, &periods, &path, args.overwrite)?;
+    }
+
+    if args.percent_of_total {
+        data = data
+            .iter()
+            .map(|worksheet_data| normalize_to_percent(worksheet_data, &periods))
+            .collect();
+        draw_config.show_total = false;
+    }
+
+    if let ViewMode::Hierarchical = args.view_mode {
+        let separator = args.hierarchy_separator;
+        data = data
+            .into_iter()
+            .map(|worksheet_data| {
+                flatten_hierarchy(&hierarchical_categories(worksheet_data, separator), separator)
+            })
+            .collect();
+        draw_config.hierarchy_separator = Some(separator);
+    }
+
+    if let ViewMode::ByTag = args.view_mode {
+        data = data
+            .into_iter()
+            .map(|worksheet_data| tag_totals(group_categories_by_tag(config, worksheet_data)))
+            .collect();
+    }
+
+    let view: Option<fn(&WorksheetData, &[Period]) -> WorksheetData> = match args.view_mode {
+        ViewMode::Absolute | ViewMode::Hierarchical | ViewMode::ByTag => None,
+        ViewMode::Share => Some(pivot_to_category_share),
+        ViewMode::DeltaPct => Some(compute_period_over_period_delta),
+        ViewMode::DeltaAbs => Some(compute_period_over_period_abs_delta),
+        ViewMode::ZScore => Some(compute_zscore_table),
+    };
+    if let Some(view) = view {
+        data = data
+            .iter()
+            .map(|worksheet_data| {
+                let mut viewed = view(worksheet_data, &periods);
+                for by_cat in viewed.values_mut() {
+                    by_cat.retain(|_period, value| !value.is_nan());
+                }
+                viewed
+            })
+            .collect();
+        draw_config.show_total = false;
+    }
+    if let ViewMode::DeltaPct | ViewMode::DeltaAbs = args.view_mode {
+        if !periods.is_empty() {
+            periods.remove(0);
+        }
+    }
+
+    draw(data, &periods, &draw_config)?;
     Ok(String::from(""))
 }
 
+fn print_stats(
+    args: &Args,
+    stats: &Stats,
+    data: Vec<WorksheetData>,
+    periods: Vec<Period>,
+) -> Result<String, MyCustomError> {
+    let currency = currency_format(args);
+    let money = |amount: f64| format_currency(amount, &currency.symbol, currency.position);
+    let budgets = budgets(args, &periods)?;
+
+    for worksheet_data in data {
+        let mut rows = category_stats(&worksheet_data, &periods);
+        match stats.sort_by {
+            StatsSortBy::Category => {}
+            StatsSortBy::Mean => {
+                rows.sort_by(|a, b| b.mean.partial_cmp(&a.mean).unwrap_or(Ordering::Equal))
+            }
+            StatsSortBy::Growth => rows.sort_by(|a, b| {
+                b.growth_rate
+                    .partial_cmp(&a.growth_rate)
+                    .unwrap_or(Ordering::Equal)
+            }),
+        }
+
+        println!(
+            "{:<30} {:>16} {:>16} {:>16} {:>16} {:>16} {:>10}",
+            "Category", "Mean", "Median", "Min", "Max", "IQR", "Growth"
+        );
+        for row in &rows {
+            println!(
+                "{:<30} {:>16} {:>16} {:>16} {:>16} {:>16} {:>9.1}%",
+                row.category,
+                money(row.mean),
+                money(row.median),
+                money(row.min),
+                money(row.max),
+                money(row.iqr),
+                row.growth_rate * 100.0
+            );
+        }
+        println!();
+
+        if stats.show_box_stats {
+            let mut table = Table::new();
+            table.set_header(vec!["Category", "P5", "Q1", "Median", "Q3", "P95"]);
+            for row in &rows {
+                table.add_row(vec![
+                    row.category.to_string(),
+                    money(row.p5),
+                    money(row.p25),
+                    money(row.median),
+                    money(row.p75),
+                    money(row.p95),
+                ]);
+            }
+            println!("{}", table);
+        }
+        println!("Gini coefficient: {:.2}", worksheet_data_summary(&worksheet_data, &periods).gini);
+        println!();
+
+        if stats.show_anomalies {
+            let sigma = args.anomaly_sigma.unwrap_or(ANOMALY_SIGMA);
+            let mut table = Table::new();
+            table.set_header(vec!["Category", "Period", "Value", "Z-score"]);
+            for anomaly in detect_anomalies(&worksheet_data, &periods, sigma) {
+                table.add_row(vec![
+                    anomaly.category.to_string(),
+                    anomaly.period.to_string(),
+                    money(anomaly.value),
+                    format!("{:+.2}", anomaly.z_score),
+                ]);
+            }
+            println!("{}", table);
+        }
+
+        if stats.show_budget_variance {
+            let mut records = compute_monthly_budget_variance(&worksheet_data, &budgets, &periods);
+            let score = compute_budget_adherence_score(&records);
+            println!("Budget Score: {:.0}/100 ({})", score, budget_grade(score));
+            records.sort_by(|a, b| b.variance.partial_cmp(&a.variance).unwrap_or(Ordering::Equal));
+            let mut table = Table::new();
+            table.set_header(vec!["Category", "Period", "Actual", "Budget", "Variance", "Over, %"]);
+            for record in records {
+                table.add_row(vec![
+                    record.category.to_string(),
+                    record.period.to_string(),
+                    money(record.actual),
+                    money(record.budget),
+                    money(record.variance),
+                    format!("{:+.1}", record.pct_over_budget),
+                ]);
+            }
+            println!("{}", table);
+        }
+
+        if stats.delta && periods.len() > 1 {
+            let mut table = Table::new();
+            let mut header = vec![String::from("Category")];
+            header.extend(periods[1..].iter().map(|p| p.to_string()));
+            table.set_header(header);
+            for (cat, by_cat) in compute_period_over_period_delta(&worksheet_data, &periods) {
+                let mut row = vec![cat.to_string()];
+                row.extend(by_cat.values().map(|delta| format!("{:+.1}%", delta)));
+                table.add_row(row);
+            }
+            println!("{}", table);
+        }
+
+        if stats.per_day_rate {
+            let mut table = Table::new();
+            table.set_header(vec!["Category", "Per day"]);
+            let group = group_by(args, &periods);
+            for (cat, rate) in compute_spending_velocity(&worksheet_data, &periods, group) {
+                table.add_row(vec![cat.to_string(), money(rate)]);
+            }
+            println!("{}", table);
+        }
+
+        if stats.seasonality {
+            let index = seasonality_index(&worksheet_data, &periods, group_by(args, &periods));
+            let len = index.values().map(|values| values.len()).max().unwrap_or(0);
+            let mut table = Table::new();
+            let mut header = vec![String::from("Category")];
+            header.extend((1..=len).map(|pos| pos.to_string()));
+            table.set_header(header);
+            for (cat, values) in index {
+                let mut row = vec![cat.to_string()];
+                row.extend(values.iter().map(|value| format!("{:.2}", value)));
+                table.add_row(row);
+            }
+            println!("{}", table);
+        }
+
+        if stats.correlation_matrix {
+            let correlation = category_correlation(&worksheet_data, &periods);
+            let mut table = Table::new();
+            let mut header = vec![String::new()];
+            header.extend(worksheet_data.keys().map(|cat| cat.to_string()));
+            table.set_header(header);
+            for a in worksheet_data.keys() {
+                let mut row = vec![Cell::new(a)];
+                for b in worksheet_data.keys() {
+                    let r = correlation[&(a.clone(), b.clone())];
+                    let cell = Cell::new(format!("{:.2}", r));
+                    row.push(if a != b && r.abs() > 0.8 {
+                        cell.add_attribute(Attribute::Bold)
+                    } else {
+                        cell
+                    });
+                }
+                table.add_row(row);
+            }
+            println!("{}", table);
+        }
+
+        if let Some(n) = stats.top_periods {
+            let mut table = Table::new();
+            table.set_header(vec!["", "Period", "Total"]);
+            for (period, total) in top_spending_months(&worksheet_data, &periods, n) {
+                table.add_row(vec![String::from("top"), period.to_string(), money(total)]);
+            }
+            for (period, total) in bottom_spending_months(&worksheet_data, &periods, n) {
+                table.add_row(vec![String::from("bottom"), period.to_string(), money(total)]);
+            }
+            println!("{}", table);
+        }
+    }
+    Ok(String::from(""))
+}
+
+fn print_comparison(
+    args: &Args,
+    compare: &Compare,
+    data: Vec<WorksheetData>,
+) -> Result<String, MyCustomError> {
+    let all_periods = worksheet_data_to_periods(&data);
+    for period in vec![&compare.period1, &compare.period2] {
+        if !all_periods.contains(period) {
+            return Err(MyCustomError::UnknownPeriod(period.clone()));
+        }
+    }
+
+    let currency = currency_format(args);
+    let money = |amount: f64| format_currency(amount, &currency.symbol, currency.position);
+
+    for worksheet_data in data {
+        let mut table = Table::new();
+        table.set_header(vec![
+            String::from("Category"),
+            compare.period1.to_string(),
+            compare.period2.to_string(),
+            String::from("Delta"),
+            String::from("Delta, %"),
+        ]);
+        for row in compare_periods(&worksheet_data, &compare.period1, &compare.period2) {
+            table.add_row(vec![
+                row.category.to_string(),
+                money(row.v1),
+                money(row.v2),
+                money(row.delta),
+                format!("{:.1}", row.delta_pct),
+            ]);
+        }
+        println!("{}", table);
+    }
+    Ok(String::from(""))
+}
+
+fn redraw(args: &Args, config: &Config) -> Result<String, MyCustomError> {
+    let (data, periods) = load_data(args, config)?;
+    draw_images(args, config, data, periods)
+}
+
+fn watch(args: &Args, config: &Config) -> Result<String, MyCustomError> {
+    let file = input_file(args)?;
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<Event>| {
+        let _ = tx.send(res);
+    })?;
+    watcher.watch(Path::new(file), RecursiveMode::NonRecursive)?;
+
+    redraw(args, config)?;
+    for res in rx.iter() {
+        if !matches!(res?.kind, EventKind::Modify(ModifyKind::Data(_))) {
+            continue;
+        }
+        while rx.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+
+        eprintln!("[{}] {} changed, redrawing", Local::now().format("%Y-%m-%d %H:%M:%S"), file);
+        if let Err(e) = redraw(args, config) {
+            error!(error = %e, "redraw failed");
+        }
+    }
+    Ok(String::from(""))
+}
+
+fn draw_diff(args: &Args, diff: &Diff, config: &Config) -> Result<String, MyCustomError> {
+    let (before, before_periods) = load_file(args, config, &diff.file_before)?;
+    let (after, after_periods) = load_file(args, config, &diff.file_after)?;
+
+    let periods: Vec<Period> = before_periods
+        .into_iter()
+        .chain(after_periods)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if diff.table {
+        let currency = currency_format(args);
+        let money = |amount: f64| format_currency(amount, &currency.symbol, currency.position);
+        for (before, after) in before.iter().zip(after.iter()) {
+            let mut table = Table::new();
+            table.set_header(vec!["Category", "Before", "After", "Change, %"]);
+            for row in diff_category_totals(before, after, &periods) {
+                table.add_row(vec![
+                    row.category.to_string(),
+                    money(row.before),
+                    money(row.after),
+                    format!("{:+.1}", row.change_pct),
+                ]);
+            }
+            println!("{}", table);
+        }
+        return Ok(String::from(""));
+    }
+
+    let data = before
+        .iter()
+        .zip(after.iter())
+        .map(|(before, after)| diff_worksheet_data(before, after, &periods))
+        .collect();
+
+    draw_images(args, config, data, periods)
+}
+
+fn write_report(
+    args: &Args,
+    report: &Report,
+    data: Vec<WorksheetData>,
+    periods: Vec<Period>,
+) -> Result<String, MyCustomError> {
+    let report_config = ReportConfig {
+        top_n: report.top,
+        anomaly_sigma: args.anomaly_sigma.unwrap_or(ANOMALY_SIGMA),
+        budgets: budgets(args, &periods)?,
+        color_mode: args.color_mode,
+        currency: currency_format(args),
+        periods,
+        sheet_names: sheet_names(args)?,
+        sheet_aliases: sheet_aliases(args)?,
+    };
+    let path = output_path(args, &report.output);
+    generate_full_report(data, &report_config, &path)?;
+    Ok(format!("{}", path.display()))
+}
+
+fn check(
+    check: &Check,
+    data: Vec<WorksheetData>,
+    periods: Vec<Period>,
+) -> Result<String, MyCustomError> {
+    let schema = read_schema(&check.schema)?;
+
+    let mut violations = 0;
+    for (sheet, worksheet_data) in data.iter().enumerate() {
+        for violation in validate_against_schema(worksheet_data, &periods, &schema) {
+            println!("sheet {}: {}", sheet, violation);
+            violations += 1;
+        }
+    }
+
+    if violations > 0 {
+        error!(violations = violations, "schema check failed");
+        std::process::exit(1);
+    }
+    Ok(String::from(""))
+}
+
+fn run(args: Args) -> Result<String, MyCustomError> {
+    if args.generate_config {
+        print!("{}", generate_config()?);
+        return Ok(String::from(""));
+    }
+
+    let config = match &args.config {
+        Some(path) => read_config(path)?,
+        None => Config::default(),
+    };
+    let args = merge_config(args, &config)?;
+
+    if let Some(Command::GenerateSample(sample)) = &args.command {
+        let categories: Vec<&str> = sample.categories.iter().map(String::as_str).collect();
+        let path = output_path(&args, &sample.output);
+        generate_sample_xlsx(&path, sample.months, &categories)?;
+        return Ok(format!("{}", path.display()));
+    }
+
+    if let Some(Command::ListSheets) = &args.command {
+        for name in list_sheets(input_file(&args)?)? {
+            println!("{}", name);
+        }
+        return Ok(String::from(""));
+    }
+
+    if let Some(Command::Inspect) = &args.command {
+        let date = |date: Option<NaiveDate>| date.map(|d| d.to_string()).unwrap_or_default();
+        let mut table = Table::new();
+        table.set_header(vec!["Sheet", "Rows", "Headers", "First date", "Last date"]);
+        for sheet in read_workbook_metadata(input_file(&args)?)?.sheets {
+            table.add_row(vec![
+                sheet.name,
+                sheet.rows.to_string(),
+                sheet.headers.join(", "),
+                date(sheet.first_date),
+                date(sheet.last_date),
+            ]);
+        }
+        println!("{}", table);
+        return Ok(String::from(""));
+    }
+
+    if let Some(Command::Diff(diff)) = &args.command {
+        return draw_diff(&args, diff, &config);
+    }
+
+    if args.watch && args.command.is_none() {
+        return watch(&args, &config);
+    }
+
+    #[cfg(feature = "tui")]
+    if args.interactive_tui && args.command.is_none() {
+        if tui::is_tty() {
+            let (data, periods) = load_data(&args, &config)?;
+            tui::run(&merge_worksheet_data(data), &periods)?;
+            return Ok(String::from(""));
+        }
+        tracing::warn!("stdout is not a terminal, falling back to the chart");
+    }
+
+    let (data, periods) = load_data(&args, &config)?;
+
+    match &args.command {
+        None => draw_images(&args, &config, data, periods),
+        Some(Command::Stats(stats)) => print_stats(&args, stats, data, periods),
+        Some(Command::Compare(compare)) => print_comparison(&args, compare, data),
+        Some(Command::Check(schema_check)) => check(schema_check, data, periods),
+        Some(Command::Report(report)) => write_report(&args, report, data, periods),
+        Some(Command::Diff(_))
+        | Some(Command::ListSheets)
+        | Some(Command::GenerateSample(_))
+        | Some(Command::Inspect) => unreachable!(),
+    }
+}
+
 fn main() {
     let args = Args::parse();
 
-    let res = draw_images(args.file, args.group_by);
-    println!("res = {:?}", res);
+    tracing_subscriber::fmt()
+        .with_max_level(args.log_level)
+        .with_writer(std::io::stderr)
+        .init();
+
+    let span = info_span!("money_manager", file = ?args.file);
+    let _enter = span.enter();
+
+    match run(args) {
+        Ok(_) => info!("done"),
+        Err(e) => {
+            error!(error = %e, "failed");
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+    fn with_env<T>(vars: &[(&str, &str)], f: impl FnOnce() -> T) -> T {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let previous: Vec<(&str, Option<std::ffi::OsString>)> =
+            vars.iter().map(|(name, _)| (*name, std::env::var_os(name))).collect();
+        for (name, value) in vars {
+            std::env::set_var(name, value);
+        }
+
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(f));
+
+        for (name, value) in previous {
+            match value {
+                Some(value) => std::env::set_var(name, value),
+                None => std::env::remove_var(name),
+            }
+        }
+        result.unwrap_or_else(|e| std::panic::resume_unwind(e))
+    }
+
+    #[test]
+    fn args_fall_back_to_environment_variables() {
+        let vars = [("MONEY_MANAGER_FILE", "env.xlsx"), ("MONEY_MANAGER_CHART_WIDTH", "800")];
+        with_env(&vars, || {
+            let args = Args::try_parse_from(&["money_manager"]).unwrap();
+            assert_eq!(args.file.as_deref(), Some("env.xlsx"));
+            assert_eq!(args.chart_width, 800);
+
+            let args = Args::try_parse_from(&["money_manager", "--file", "cli.xlsx"]).unwrap();
+            assert_eq!(args.file.as_deref(), Some("cli.xlsx"));
+        });
+    }
 }
@@ -1,19 +1,70 @@
 use calamine::{open_workbook, DataType, Range, Reader, Xlsx, XlsxError};
-use chrono::{Datelike, NaiveDate};
-use plotly::common::{DashType, Line, Mode, Title};
-use plotly::{ImageFormat, Layout, Plot, Scatter};
+use chrono::{Datelike, Duration, NaiveDate};
+use csv::{ReaderBuilder, WriterBuilder};
+use plotly::common::color::Rgb;
+use plotly::common::{DashType, Fill, Font, Line, Marker, Mode, Title, Visible};
+use plotly::layout::{Annotation, Axis, AxisSide, BarMode, Legend, Shape, ShapeLine, ShapeType};
+use plotly::{Bar, ImageFormat, Layout, Plot, Scatter, Trace};
 use rand::Rng;
-use statistical::{mean, median};
+use regex::Regex;
+use rusqlite::{params, Connection};
+#[cfg(feature = "ndarray")]
+use ndarray::Array2;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use statistical::{mean, median, population_standard_deviation};
+use std::borrow::Borrow;
+use std::cmp::Ordering;
 use std::fmt;
-use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::io::{BufRead, Write};
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+use xlsxwriter::{Format, FormatColor, Workbook, Worksheet};
 
-const MAX_PERIODS: usize = 12;
-const FILENAME_LEN: usize = 16;
+#[cfg(feature = "streaming")]
+mod streaming;
+
+pub const MAX_PERIODS: usize = 12;
+pub const RECURRING_THRESHOLD: f64 = 0.75;
+pub const ANOMALY_SIGMA: f64 = 2.0;
+pub const DEFAULT_METRIC_NAME: &str = "spending_amount";
+const MIN_CHART_SIZE: u32 = 200;
+const THIRD_AXIS_X_DOMAIN_END: f64 = 0.9;
+const INFER_SAMPLE_ROWS: usize = 5;
+const INFER_CONFIDENCE: f64 = 0.8;
+const VALUE_EPSILON: f64 = 1e-9;
+const DARK_MODE_FOREGROUND: [u8; 3] = [230, 230, 230];
+const CURRENCY_CODES: &[&str] = &[
+    "RUB", "USD", "EUR", "GBP", "CHF", "CNY", "JPY", "KZT", "BYN", "UAH", "AMD", "GEL", "TRY",
+    "AED", "THB", "CZK", "PLN", "SEK", "NOK", "DKK", "CAD", "AUD", "ILS", "INR", "UZS", "KGS",
+];
 
 #[derive(Debug)]
 pub enum MyCustomError {
     OpenError,
+    ConfigError(String),
+    UnknownTxType(String),
+    InvalidConfig(String),
+    DbError(String),
+    IoError(io::Error),
+    NoSpendingCategories,
+    EmptyPeriods,
+    UnknownPeriod(Period),
+    MissingExchangeRate { currency: String, period: String },
+    WatchError(notify::Error),
+    DuplicatesFound(usize),
+    HtmlExportError(String),
+    MetricsExportError(String),
+    XlsxFormatError(String),
+    MissingCpiData { period: Period },
     OtherError,
 }
 
@@ -23,490 +74,5766 @@ impl From<XlsxError> for MyCustomError {
     }
 }
 
+impl From<io::Error> for MyCustomError {
+    fn from(e: io::Error) -> Self {
+        MyCustomError::IoError(e)
+    }
+}
+
+impl From<csv::Error> for MyCustomError {
+    fn from(e: csv::Error) -> Self {
+        MyCustomError::IoError(e.into())
+    }
+}
+
+impl From<xlsxwriter::XlsxError> for MyCustomError {
+    fn from(e: xlsxwriter::XlsxError) -> Self {
+        MyCustomError::IoError(io::Error::new(io::ErrorKind::Other, e.to_string()))
+    }
+}
+
+impl From<serde_json::Error> for MyCustomError {
+    fn from(e: serde_json::Error) -> Self {
+        MyCustomError::IoError(e.into())
+    }
+}
+
+impl From<rusqlite::Error> for MyCustomError {
+    fn from(e: rusqlite::Error) -> Self {
+        MyCustomError::DbError(e.to_string())
+    }
+}
+
+impl From<notify::Error> for MyCustomError {
+    fn from(e: notify::Error) -> Self {
+        MyCustomError::WatchError(e)
+    }
+}
+
 impl fmt::Display for MyCustomError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             MyCustomError::OpenError => write!(f, "Can't open file"),
+            MyCustomError::ConfigError(e) => write!(f, "Can't read config: {}", e),
+            MyCustomError::UnknownTxType(s) => write!(f, "Unknown transaction type '{}'", s),
+            MyCustomError::InvalidConfig(e) => write!(f, "Invalid config: {}", e),
+            MyCustomError::DbError(e) => write!(f, "Database error: {}", e),
+            MyCustomError::IoError(e) => write!(f, "I/O error: {}", e.to_string()),
+            MyCustomError::NoSpendingCategories => write!(f, "No spending categories to draw"),
+            MyCustomError::EmptyPeriods => write!(f, "No periods to draw"),
+            MyCustomError::UnknownPeriod(p) => write!(f, "Period '{}' not found", p),
+            MyCustomError::MissingExchangeRate { currency, period } => {
+                write!(f, "No exchange rate for {} in period '{}'", currency, period)
+            }
+            MyCustomError::WatchError(e) => write!(f, "Can't watch file: {}", e),
+            MyCustomError::DuplicatesFound(n) => write!(f, "Found {} duplicate transactions", n),
+            MyCustomError::HtmlExportError(e) => write!(f, "Can't export HTML: {}", e),
+            MyCustomError::MetricsExportError(e) => write!(f, "Can't export metrics: {}", e),
+            MyCustomError::XlsxFormatError(e) => write!(f, "Can't read workbook: {}", e),
+            MyCustomError::MissingCpiData { period } => {
+                write!(f, "No CPI index for period '{}'", period)
+            }
             MyCustomError::OtherError => write!(f, "Other error"),
         }
     }
 }
 
-#[derive(Debug)]
-struct Columns {
-    period: usize,
-    category: usize,
-    tx_type: usize,
-    value: usize,
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub file: Option<String>,
+    pub group_by: Option<String>,
+    pub max_periods: Option<usize>,
+    pub output_dir: Option<String>,
+    pub chart_type: Option<String>,
+    pub palette: Option<String>,
+    pub sheet_filter: Option<String>,
+    pub column_headers: Option<ColumnHeaders>,
+    pub tx_type_labels: Option<TxTypeLabels>,
+    #[serde(alias = "aliases")]
+    pub category_aliases: HashMap<String, String>,
+    pub tags: BTreeMap<String, Vec<String>>,
 }
 
-#[derive(Debug)]
-enum TxType {
-    Income,
-    Outcome,
+pub fn read_config(file: &str) -> Result<Config, MyCustomError> {
+    let content = fs::read_to_string(file)
+        .map_err(|e| MyCustomError::ConfigError(e.to_string()))?;
+    toml::from_str(&content).map_err(|e| MyCustomError::ConfigError(e.to_string()))
 }
 
-#[derive(Debug)]
-struct Fields {
-    period: NaiveDate,
-    category: String,
-    tx_type: TxType,
-    value: f64,
+pub fn generate_config() -> Result<String, MyCustomError> {
+    let config = Config {
+        group_by: Some(GroupBy::Month.to_string()),
+        max_periods: Some(MAX_PERIODS),
+        chart_type: Some(String::from("line")),
+        palette: Some(String::from("default")),
+        column_headers: Some(ColumnHeaders::default()),
+        tx_type_labels: Some(TxTypeLabels::default()),
+        ..Config::default()
+    };
+    toml::to_string_pretty(&config).map_err(|e| MyCustomError::ConfigError(e.to_string()))
 }
 
-pub enum GroupBy {
-    Month,
-    Quarter,
-    Year,
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColumnHeaders {
+    pub period: String,
+    pub category: String,
+    pub tx_type: String,
+    pub value: String,
 }
 
-type Period = String;
-type Category = String;
-
-fn by_month(date: NaiveDate) -> Period {
-    format!("{:04}-{:02}", date.year(), date.month())
+impl Default for ColumnHeaders {
+    fn default() -> Self {
+        ColumnHeaders {
+            period: String::from("Период"),
+            category: String::from("Категория"),
+            tx_type: String::from("Доход/Расход"),
+            value: String::from("RUB"),
+        }
+    }
 }
 
-fn by_quarter(date: NaiveDate) -> Period {
-    format!("{:04}-q{}", date.year(), (date.month() - 1) / 3 + 1)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Columns {
+    pub period: usize,
+    pub category: usize,
+    pub tx_type: usize,
+    pub value: usize,
 }
 
-fn by_year(date: NaiveDate) -> Period {
-    format!("{:04}", date.year())
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TxType {
+    Income,
+    Outcome,
+    Transfer,
 }
 
-fn period_from_date(group_by: GroupBy) -> fn(NaiveDate) -> String {
-    match group_by {
-        GroupBy::Year => |date| by_year(date),
-        GroupBy::Quarter => |date| by_quarter(date),
-        GroupBy::Month => |date| by_month(date),
+impl TxType {
+    pub fn label<'a>(&self, labels: &'a TxTypeLabels) -> &'a str {
+        match self {
+            TxType::Income => &labels.income,
+            TxType::Outcome => &labels.outcome,
+            TxType::Transfer => &labels.transfer,
+        }
     }
 }
 
-fn read_row(columns: &Columns, row: &[DataType]) -> Result<Fields, String> {
-    let mut period = None;
-    if let DataType::String(s) = &row[columns.period] {
-        if let Ok(date) = NaiveDate::parse_from_str(&s, "%d.%m.%Y") {
-            period = Some(date);
+impl fmt::Display for TxType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TxType::Income => write!(f, "income"),
+            TxType::Outcome => write!(f, "outcome"),
+            TxType::Transfer => write!(f, "transfer"),
         }
     }
-    if period == None {
-        return Err(format!(
-            "Can't read period from {:?}",
-            row[columns.period]
-        ));
-    }
+}
 
-    let mut category = None;
-    if let DataType::String(s) = &row[columns.category] {
-        category = Some(s);
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TxTypeLabels {
+    pub income: String,
+    pub outcome: String,
+    pub transfer: String,
+    pub extra_income: Vec<String>,
+}
+
+impl Default for TxTypeLabels {
+    fn default() -> Self {
+        TxTypeLabels {
+            income: String::from("Доход"),
+            outcome: String::from("Расход"),
+            transfer: String::from("Перевод"),
+            extra_income: Vec::new(),
+        }
     }
-    if category == None {
-        return Err(format!(
-            "Can't read category from {:?}",
-            row[columns.category]
-        ));
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ParseOptions {
+    pub column_headers: ColumnHeaders,
+    pub tx_type_labels: TxTypeLabels,
+    pub include_transfers: bool,
+    pub sheet_filter: Option<Regex>,
+    pub currency_conversion: Option<CurrencyConversion>,
+    pub skip_rows: usize,
+    pub strict_headers: bool,
+    pub warn_duplicates: bool,
+    pub deduplicate: bool,
+    pub strict_duplicates: bool,
+    pub lenient: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CurrencyMap {
+    rates: HashMap<(Period, String, String), f64>,
+}
+
+impl CurrencyMap {
+    pub fn insert(&mut self, period: Period, from: &str, to: &str, rate: f64) {
+        self.rates
+            .insert((period, from.to_uppercase(), to.to_uppercase()), rate);
     }
 
-    let income = String::from("Доход");
-    let outcome = String::from("Расход");
-    let mut tx_type = None;
-    if let DataType::String(s) = &row[columns.tx_type] {
-        if *s == income {
-            tx_type = Some(TxType::Income);
-        } else if *s == outcome {
-            tx_type = Some(TxType::Outcome);
-        } else {
-            return Err(format!(
-                "Can't read transaction type from {:?}",
-                row[columns.tx_type]
-            ));
+    pub fn rate(&self, period: &Period, from: &str, to: &str) -> Option<f64> {
+        let from = from.to_uppercase();
+        let to = to.to_uppercase();
+        if from == to {
+            return Some(1.0);
         }
+        if let Some(rate) = self.rates.get(&(period.clone(), from.clone(), to.clone())) {
+            return Some(*rate);
+        }
+        self.rates
+            .get(&(period.clone(), to, from))
+            .filter(|rate| **rate != 0.0)
+            .map(|rate| 1.0 / rate)
     }
+}
 
-    let mut value = None;
-    if let DataType::Float(f) = &row[columns.value] {
-        value = Some(f);
-    }
-    if value == None {
-        return Err(format!(
-            "Can't read value from {:?}",
-            row[columns.value]
-        ));
+#[derive(Debug, Clone)]
+pub struct CurrencyConversion {
+    pub rates: CurrencyMap,
+    pub target: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Fields {
+    pub period: NaiveDate,
+    pub category: Category,
+    pub tx_type: TxType,
+    pub value: f64,
+    pub period_defaulted: bool,
+}
+
+impl PartialEq for Fields {
+    fn eq(&self, other: &Self) -> bool {
+        self.period == other.period
+            && self.category == other.category
+            && self.tx_type == other.tx_type
+            && (self.value - other.value).abs() < VALUE_EPSILON
     }
+}
 
-    Ok(Fields {
-        period: period.unwrap(),
-        category: category.unwrap().to_string(),
-        tx_type: tx_type.unwrap(),
-        value: *value.unwrap(),
-    })
+impl fmt::Display for Fields {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}\t{}\t{}\t{}", self.period, self.category, self.tx_type, self.value)
+    }
 }
 
-type WorksheetData = BTreeMap<Category, BTreeMap<Period, f64>>;
+pub type PeriodFn = Arc<dyn Fn(NaiveDate) -> Period + Send + Sync>;
 
-fn read_worksheet(
-    name: String,
-    range: Range<DataType>,
-    group_by: fn(NaiveDate) -> Period,
-) -> Result<WorksheetData, MyCustomError> {
-    let period_str = "Период";
-    let category_str = "Категория";
-    let tx_type_str = "Доход/Расход";
-    let value_str = "RUB";
-
-    let period_dt = DataType::String(String::from(period_str));
-    let category_dt = DataType::String(String::from(category_str));
-    let tx_type_dt = DataType::String(String::from(tx_type_str));
-    let value_dt = DataType::String(String::from(value_str));
-
-    let mut period_pos = None;
-    let mut category_pos = None;
-    let mut tx_type_pos = None;
-    let mut value_pos = None;
-
-    if let Some(first_row) = range.rows().next() {
-        for i in 0..first_row.len() - 1 {
-            if first_row[i] == period_dt {
-                period_pos = Some(i);
-            } else if first_row[i] == category_dt {
-                category_pos = Some(i);
-            } else if first_row[i] == tx_type_dt {
-                tx_type_pos = Some(i);
-            } else if first_row[i] == value_dt {
-                value_pos = Some(i);
+#[derive(Clone)]
+pub enum GroupBy {
+    Month,
+    Quarter,
+    Year,
+    RollingQuarter,
+    WeekOfMonth,
+    Custom(PeriodFn),
+}
+
+impl PartialEq for GroupBy {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (GroupBy::Month, GroupBy::Month)
+            | (GroupBy::Quarter, GroupBy::Quarter)
+            | (GroupBy::Year, GroupBy::Year)
+            | (GroupBy::RollingQuarter, GroupBy::RollingQuarter)
+            | (GroupBy::WeekOfMonth, GroupBy::WeekOfMonth) => true,
+            (GroupBy::Custom(a), GroupBy::Custom(b)) => {
+                Arc::as_ptr(a) as *const () == Arc::as_ptr(b) as *const ()
             }
+            _ => false,
         }
-    } else {
-        //return Err(format!("Can't read first row from sheet '{}'", name));
-        return Err(MyCustomError::OtherError)
     }
+}
 
-    if period_pos == None {
-        return Err(MyCustomError::OtherError)
-//        return Err(format!(
-//            "Can't find column '{}' in sheet '{}'",
-//            period_str, name
-//        ));
+impl fmt::Debug for GroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GroupBy::Month => write!(f, "Month"),
+            GroupBy::Quarter => write!(f, "Quarter"),
+            GroupBy::Year => write!(f, "Year"),
+            GroupBy::RollingQuarter => write!(f, "RollingQuarter"),
+            GroupBy::WeekOfMonth => write!(f, "WeekOfMonth"),
+            GroupBy::Custom(_) => write!(f, "Custom(..)"),
+        }
     }
-    if category_pos == None {
-        return Err(MyCustomError::OtherError)
-//        return Err(format!(
-//            "Can't find column '{}' in sheet '{}'",
-//            category_str, name
-//        ));
+}
+
+impl fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GroupBy::Month => write!(f, "month"),
+            GroupBy::Quarter => write!(f, "quarter"),
+            GroupBy::Year => write!(f, "year"),
+            GroupBy::RollingQuarter => write!(f, "rolling-quarter"),
+            GroupBy::WeekOfMonth => write!(f, "week-of-month"),
+            GroupBy::Custom(_) => write!(f, "custom"),
+        }
     }
-    if tx_type_pos == None {
-        return Err(MyCustomError::OtherError)
-//        return Err(format!(
-//            "Can't find column '{}' in sheet '{}'",
-//            tx_type_str, name
-//        ));
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupByParseError(pub String);
+
+impl fmt::Display for GroupByParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown grouping '{}'", self.0)
     }
-    if value_pos == None {
-        return Err(MyCustomError::OtherError)
-//        return Err(format!(
-//            "Can't fund column '{}' in sheet '{}'",
-//            value_str, name
-//        ));
+}
+
+impl std::error::Error for GroupByParseError {}
+
+impl From<GroupByParseError> for MyCustomError {
+    fn from(e: GroupByParseError) -> Self {
+        MyCustomError::InvalidConfig(e.to_string())
     }
+}
 
-    let columns = Columns {
-        period: period_pos.unwrap(),
-        category: category_pos.unwrap(),
-        tx_type: tx_type_pos.unwrap(),
-        value: value_pos.unwrap(),
-    };
+impl FromStr for GroupBy {
+    type Err = GroupByParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "month" => Ok(GroupBy::Month),
+            "quarter" => Ok(GroupBy::Quarter),
+            "year" => Ok(GroupBy::Year),
+            "rolling-quarter" => Ok(GroupBy::RollingQuarter),
+            "week-of-month" => Ok(GroupBy::WeekOfMonth),
+            _ => Err(GroupByParseError(s.to_string())),
+        }
+    }
+}
 
-    let mut by_category: BTreeMap<Category, BTreeMap<Period, f64>> = BTreeMap::new();
+macro_rules! string_newtype {
+    ($name:ident) => {
+        #[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(String);
 
-    for row in range.rows() {
-        if let Ok(fields) = read_row(&columns, row) {
-            let period = group_by(fields.period);
+        impl $name {
+            pub fn as_str(&self) -> &str {
+                &self.0
+            }
+        }
 
-            let addition = match fields.tx_type {
-                TxType::Outcome => fields.value,
-                TxType::Income => -fields.value,
-            };
+        impl Deref for $name {
+            type Target = str;
 
-            *by_category
-                .entry(fields.category)
-                .or_insert(BTreeMap::new())
-                .entry(period)
-                .or_insert(0.0) += addition;
+            fn deref(&self) -> &str {
+                &self.0
+            }
         }
-    }
 
-    Ok(by_category)
+        impl Borrow<str> for $name {
+            fn borrow(&self) -> &str {
+                &self.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Display::fmt(&self.0, f)
+            }
+        }
+
+        impl From<String> for $name {
+            fn from(s: String) -> Self {
+                $name(s)
+            }
+        }
+
+        impl From<&str> for $name {
+            fn from(s: &str) -> Self {
+                $name(s.to_string())
+            }
+        }
+    };
 }
 
-fn last_n_groups(periods: Vec<Period>, n: usize) -> Vec<Period> {
-    periods
-        .into_iter()
-        .rev()
-        .take(n + 1)
-        .skip(1)
-        .rev()
-        .collect()
+string_newtype!(Period);
+string_newtype!(Category);
+
+fn by_month(date: NaiveDate) -> Period {
+    Period::from(format!("{:04}-{:02}", date.year(), date.month()))
 }
 
-/*
-#[derive(Debug, Clone)]
-struct ChartData {
-    name: String,
-    y_values: Vec<f64>,
-    line: Line,
+fn by_quarter(date: NaiveDate) -> Period {
+    Period::from(format!("{:04}-q{}", date.year(), (date.month() - 1) / 3 + 1))
 }
 
-#[derive(Debug, Clone)]
-struct ImageData {
-    x_values: Vec<Period>,
-    charts: Vec<ChartData>,
+fn by_year(date: NaiveDate) -> Period {
+    Period::from(format!("{:04}", date.year()))
 }
 
-fn worksheet_data_to_image_data(worksheet_data: WorksheetData, periods: Vec<Period>) -> ImageData {
-    let mut chart_data = Vec::new();
+fn by_rolling_quarter(date: NaiveDate) -> Period {
+    let start = months_back(date, 2);
+    Period::from(format!("{:04}-{:02}..{:02}", start.year(), start.month(), date.month()))
+}
 
-    for (category, by_period) in &worksheet_data {
-        let y_values: Vec<_> = periods
-            .iter()
-            .map(|period| match by_period.get(period) {
-                Some(v) => *v,
-                None => 0.0,
-            })
-            .collect();
+pub fn by_week_of_month(date: NaiveDate) -> Period {
+    Period::from(format!(
+        "{:04}-{:02}-W{}",
+        date.year(),
+        date.month(),
+        (date.day() - 1) / 7 + 1
+    ))
+}
 
-        chart_data.push(ChartData {
-            name: category.to_string(),
-            y_values: y_values,
-            line: Line::new(),
-        })
-    }
+pub fn by_custom_fn<F: Fn(NaiveDate) -> Period>(date: NaiveDate, f: F) -> Period {
+    f(date)
+}
 
-    ImageData {
-        x_values: periods,
-        charts: chart_data,
+fn period_from_date(group_by: GroupBy) -> PeriodFn {
+    match group_by {
+        GroupBy::WeekOfMonth => Arc::new(by_week_of_month),
+        GroupBy::RollingQuarter => Arc::new(by_rolling_quarter),
+        GroupBy::Year => Arc::new(by_year),
+        GroupBy::Quarter => Arc::new(by_quarter),
+        GroupBy::Month => Arc::new(by_month),
+        GroupBy::Custom(f) => f,
     }
 }
 
-fn filter_all_spendings(data: Vec<ChartData>) -> Vec<ChartData> {
-    data.into_iter()
-        .filter(|cd| median(&cd.y_values) > 0.0)
-        .collect()
+pub fn infer_group_by_from_dates(dates: &[NaiveDate]) -> Option<GroupBy> {
+    if dates.is_empty() {
+        return None;
+    }
+    let all = |matches: fn(&NaiveDate) -> bool| dates.iter().all(matches);
+    Some(if all(|date| date.ordinal() == 1) {
+        GroupBy::Year
+    } else if all(|date| date.day() == 1 && date.month0() % 3 == 0) {
+        GroupBy::Quarter
+    } else {
+        GroupBy::Month
+    })
 }
 
-fn filter_regular_spendings(data: Vec<ChartData>) -> Vec<ChartData> {
-    data.into_iter()
-        .filter(|cd| {
-            let mean = mean(&cd.y_values);
-            let median = median(&cd.y_values);
-            (median > 0.0) && (mean < 2.0 * median) && (median < 2.0 * mean)
+pub fn infer_group_by_from_data(periods: &[Period]) -> GroupBy {
+    let candidates = [
+        GroupBy::WeekOfMonth,
+        GroupBy::Month,
+        GroupBy::RollingQuarter,
+        GroupBy::Quarter,
+        GroupBy::Year,
+    ];
+    let sample: Vec<&Period> = periods.iter().take(INFER_SAMPLE_ROWS).collect();
+    candidates
+        .iter()
+        .find(|group_by| {
+            sample.iter().any(|period| {
+                parse_period_string(period, (*group_by).clone())
+                    .map(|date| period_from_date((*group_by).clone())(date) == **period)
+                    .unwrap_or(false)
+            })
+        })
+        .cloned()
+        .unwrap_or_else(|| {
+            warn!(sample = ?sample, "can't infer grouping from periods, using month");
+            GroupBy::Month
         })
-        .collect()
 }
 
-*/
+fn months_in_period(group_by: &GroupBy) -> u32 {
+    match group_by {
+        GroupBy::Year => 12,
+        GroupBy::Quarter | GroupBy::RollingQuarter => 3,
+        GroupBy::Month => 1,
+        GroupBy::WeekOfMonth | GroupBy::Custom(_) => 0,
+    }
+}
 
-/*
-fn add_total(mut image_data: ImageData) -> ImageData {
-    let len = image_data.x_values.len();
+fn months_between_periods(group_by: &GroupBy) -> u32 {
+    match group_by {
+        GroupBy::RollingQuarter => 1,
+        _ => months_in_period(group_by),
+    }
+}
 
-    let mut total: Vec<f64> = vec![0.0; len];
-    for chart in &image_data.charts {
-        for i in 0..len {
-            total[i] += chart.y_values[i];
+pub fn parse_period_string(period: &Period, group_by: GroupBy) -> Option<NaiveDate> {
+    match group_by {
+        GroupBy::Year => NaiveDate::from_ymd_opt(period.parse().ok()?, 1, 1),
+        GroupBy::Quarter => {
+            let (year, quarter) = period.split_once("-q")?;
+            let quarter: u32 = quarter.parse().ok()?;
+            if !(1..=4).contains(&quarter) {
+                return None;
+            }
+            NaiveDate::from_ymd_opt(year.parse().ok()?, (quarter - 1) * 3 + 1, 1)
+        }
+        GroupBy::Month => {
+            let (year, month) = period.split_once('-')?;
+            NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, 1)
+        }
+        GroupBy::RollingQuarter => {
+            let (start, end_month) = period.split_once("..")?;
+            let (year, start_month) = start.split_once('-')?;
+            let year: i32 = year.parse().ok()?;
+            let start_month: u32 = start_month.parse().ok()?;
+            let end_month: u32 = end_month.parse().ok()?;
+            let year = if end_month < start_month { year + 1 } else { year };
+            NaiveDate::from_ymd_opt(year, end_month, 1)
         }
+        GroupBy::WeekOfMonth => {
+            let (month, week) = period.split_once("-W")?;
+            let (year, month) = month.split_once('-')?;
+            let week: u32 = week.parse().ok()?;
+            if !(1..=5).contains(&week) {
+                return None;
+            }
+            NaiveDate::from_ymd_opt(year.parse().ok()?, month.parse().ok()?, (week - 1) * 7 + 1)
+        }
+        GroupBy::Custom(_) => None,
     }
-
-    image_data.charts.push(ChartData {
-        name: String::from("Всего"),
-        y_values: total,
-        line: Line::new().dash(DashType::LongDashDot),
-    });
-
-    image_data
 }
-*/
 
-fn fix_label(s: &String) -> String {
-    s.replace(" ", "&nbsp;")
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeriodMeta {
+    pub period: String,
+    pub year: i32,
+    pub month: Option<u32>,
+    pub quarter: Option<u32>,
+    pub is_year_start: bool,
+    pub is_year_end: bool,
+    pub label: String,
 }
 
-fn generate_random_filename(len: usize) -> String {
-    const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
-    let mut rng = rand::thread_rng();
-    (0..len)
-        .map(|_| {
-            let idx = rng.gen_range(0..CHARSET.len());
-            CHARSET[idx] as char
+pub fn extract_period_metadata(periods: &[Period], group_by: GroupBy) -> Vec<PeriodMeta> {
+    let dates: Vec<(&Period, NaiveDate)> = periods
+        .iter()
+        .filter_map(|period| Some((period, parse_period_string(period, group_by.clone())?)))
+        .collect();
+
+    dates
+        .iter()
+        .enumerate()
+        .map(|(i, (period, date))| {
+            let year = date.year();
+            let (month, quarter) = match group_by {
+                GroupBy::Year => (None, None),
+                GroupBy::Quarter => (None, Some(date.month0() / 3 + 1)),
+                _ => (Some(date.month()), Some(date.month0() / 3 + 1)),
+            };
+            let year_of = |j: usize| dates.get(j).map(|(_period, date)| date.year());
+            PeriodMeta {
+                period: period.to_string(),
+                year,
+                month,
+                quarter,
+                is_year_start: i == 0 || year_of(i - 1) != Some(year),
+                is_year_end: year_of(i + 1) != Some(year),
+                label: period.to_string(),
+            }
         })
         .collect()
 }
 
-/*
-fn draw_image(
-    image_data: &ImageData,
-    title: String,
-    filter: fn(Vec<ChartData>) -> Vec<ChartData>,
-) -> Result<PathBuf, String> {
-    let filtered_image_data = ImageData {
-        x_values: image_data.x_values.clone(),
-        charts: filter(image_data.charts.clone()),
+pub fn reformat_period_label(period: &Period, group_by: GroupBy, format: &str) -> String {
+    match parse_period_string(period, group_by) {
+        Some(date) => date.format(format).to_string(),
+        None => period.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MissingPeriod {
+    pub after: Period,
+    pub expected: Period,
+}
+
+fn next_period(period: &Period, group_by: &GroupBy) -> Option<Period> {
+    let date = parse_period_string(period, group_by.clone())?;
+    let next = match group_by {
+        GroupBy::WeekOfMonth => {
+            let next = date + Duration::weeks(1);
+            if next.month() == date.month() {
+                next
+            } else {
+                next.with_day(1)?
+            }
+        }
+        _ => months_back(date, 0) + Duration::days(31 * months_between_periods(group_by) as i64),
     };
-    let filtered_image_data = add_total(filtered_image_data);
+    Some(period_from_date(group_by.clone())(next))
+}
 
-    let mut plot = Plot::new();
-    let layout = Layout::new().title(Title::new(&fix_label(&title)));
-    plot.set_layout(layout);
+pub fn validate_period_continuity(periods: &[Period], group_by: GroupBy) -> Vec<MissingPeriod> {
+    let mut sorted: Vec<&Period> = periods.iter().collect();
+    sorted.sort();
+    sorted.dedup();
 
-    for data in filtered_image_data.charts {
-        let mean = mean(&data.y_values);
-        let label = format!("{} (avg: {}k)", data.name, (mean as i32) / 1000);
-        plot.add_trace(
-            Scatter::new(image_data.x_values.to_owned(), data.y_values.to_owned())
-                .name(&fix_label(&label))
-                .mode(Mode::LinesMarkers)
-                .line(data.line),
-        );
+    let mut result = Vec::new();
+    for pair in sorted.windows(2) {
+        let (after, observed) = (pair[0], pair[1]);
+        let mut current = after.clone();
+        while let Some(expected) = next_period(&current, &group_by) {
+            if expected <= current || expected >= *observed {
+                break;
+            }
+            result.push(MissingPeriod {
+                after: after.clone(),
+                expected: expected.clone(),
+            });
+            current = expected;
+        }
     }
+    result
+}
 
-    
-    let mut filename = generate_random_filename(FILENAME_LEN);
-    filename.push_str(".svg");
-    let path = Path::new("/tmp").join(filename);
-
-    //plot.save(&path, ImageFormat::SVG, 1400, 740, 1.0);
-    plot.show();
-    Ok(path)
+fn months_back(date: NaiveDate, months: u32) -> NaiveDate {
+    let total = date.year() * 12 + date.month0() as i32 - months as i32;
+    NaiveDate::from_ymd(total.div_euclid(12), total.rem_euclid(12) as u32 + 1, 1)
 }
-*/
 
-/*
-pub fn parse_report_and_draw_images(file: String, group: GroupBy) -> Result<Vec<PathBuf>, String> {
-    let group_by = period_from_date(group);
-    let maybe_workbook: Result<Xlsx<_>, XlsxError> = open_workbook(file);
-    match maybe_workbook {
-        Err(e) => Err(e.to_string()),
-        Ok(mut workbook) => {
-            let mut result: Vec<Result<PathBuf, String>> = Vec::new();
+fn months_ahead(date: NaiveDate, months: u32) -> NaiveDate {
+    let total = date.year() * 12 + date.month0() as i32 + months as i32;
+    NaiveDate::from_ymd(total.div_euclid(12), total.rem_euclid(12) as u32 + 1, 1)
+}
 
-            for (worksheet_name, range) in workbook.worksheets() {
-                if let Ok(worksheet_data)) =
-                    read_worksheet(worksheet_name, range, group_by)
-                {
-                    let x_values: Vec<_> = last_n_groups(periods, MAX_PERIODS);
-                    let image_data = worksheet_data_to_image_data(worksheet_data, x_values);
+fn rolling_quarter_sums(monthly: WorksheetData) -> WorksheetData {
+    let last = monthly
+        .values()
+        .flat_map(|by_cat| by_cat.keys())
+        .filter_map(|period| parse_period_string(period, GroupBy::Month))
+        .max();
 
-                    result.push(draw_image(
-                        &image_data,
-                        String::from("Регулярные траты"),
-                        filter_regular_spendings,
-                    ));
-                    result.push(draw_image(
-                        &image_data,
-                        String::from("Все траты"),
-                        filter_all_spendings,
-                    ));
+    monthly
+        .into_iter()
+        .map(|(cat, by_cat)| {
+            let mut windows = BTreeMap::new();
+            for (period, value) in by_cat {
+                let month = match parse_period_string(&period, GroupBy::Month) {
+                    Some(month) => month,
+                    None => continue,
+                };
+                for end in (0..3).map(|offset| months_ahead(month, offset)) {
+                    if last.map_or(false, |last| end > last) {
+                        break;
+                    }
+                    *windows.entry(by_rolling_quarter(end)).or_insert(0.0) += value;
                 }
             }
+            (cat, windows)
+        })
+        .collect()
+}
 
-            let (results, errors): (Vec<_>, Vec<_>) = result.into_iter().partition(|r| r.is_ok());
+fn days_in_period(period: &Period, group_by: &GroupBy) -> Option<i64> {
+    let start = parse_period_string(period, group_by.clone())?;
+    let start = match group_by {
+        GroupBy::WeekOfMonth => {
+            let next_month = (months_back(start, 0) + Duration::days(31)).with_day(1)?;
+            return Some((next_month - start).num_days().min(7));
+        }
+        GroupBy::RollingQuarter => months_back(start, months_in_period(group_by) - 1),
+        _ => start,
+    };
+    let end = months_ahead(start, months_in_period(group_by));
+    Some((end - start).num_days())
+}
 
-            let errors: Vec<_> = errors.into_iter().map(Result::unwrap_err).collect();
+pub fn infer_date_range_from_data(periods: &[Period], group_by: &GroupBy, n: usize) -> (Period, Period) {
+    let last = periods.iter().max().cloned().unwrap_or_default();
+    let first = match parse_period_string(&last, group_by.clone()) {
+        Some(date) => {
+            let first_date = match group_by {
+                GroupBy::WeekOfMonth => date - Duration::weeks(n.saturating_sub(1) as i64),
+                _ => {
+                    let months = months_between_periods(group_by) * n.saturating_sub(1) as u32;
+                    months_back(date, months)
+                }
+            };
+            period_from_date(group_by.clone())(first_date)
+        }
+        None => last.clone(),
+    };
 
-            if results.len() == 0 {
-                Err(String::from("Not found sheets with report"))
-            } else if errors.len() > 0 {
-                Err(errors.join(";\n"))
-            } else {
-                Ok(results.into_iter().map(Result::unwrap).collect())
-            }
+    (first, last)
+}
+
+fn read_tx_type(s: &str, labels: &TxTypeLabels) -> Result<TxType, MyCustomError> {
+    if s == labels.income || labels.extra_income.iter().any(|label| label == s) {
+        Ok(TxType::Income)
+    } else if s == labels.outcome {
+        Ok(TxType::Outcome)
+    } else if s == labels.transfer {
+        Ok(TxType::Transfer)
+    } else {
+        Err(MyCustomError::UnknownTxType(s.to_string()))
+    }
+}
+
+fn date_from_serial(serial: f64) -> Option<NaiveDate> {
+    if serial < 0.0 {
+        return None;
+    }
+    NaiveDate::from_ymd_opt(1899, 12, 30)?.checked_add_signed(Duration::days(serial.trunc() as i64))
+}
+
+pub fn infer_columns_heuristically(
+    range: &Range<DataType>,
+    options: &ParseOptions,
+) -> Option<Columns> {
+    let rows: Vec<&[DataType]> = range
+        .rows()
+        .skip(options.skip_rows + 1)
+        .take(INFER_SAMPLE_ROWS)
+        .collect();
+    if rows.is_empty() {
+        return None;
+    }
+    let labels = &options.tx_type_labels;
+    let width = range.width();
+
+    let confidence = |col: usize, matches: &dyn Fn(&DataType) -> bool| {
+        let matched = rows
+            .iter()
+            .filter(|row| row.get(col).map_or(false, |cell| matches(cell)))
+            .count();
+        matched as f64 / rows.len() as f64
+    };
+    let is_date = |cell: &DataType| match cell {
+        DataType::String(s) => NaiveDate::parse_from_str(s, "%d.%m.%Y").is_ok(),
+        DataType::DateTime(_) => true,
+        _ => false,
+    };
+    let is_tx_type = |cell: &DataType| match cell {
+        DataType::String(s) => read_tx_type(s, labels).is_ok(),
+        _ => false,
+    };
+    let is_number = |cell: &DataType| matches!(cell, DataType::Float(_) | DataType::Int(_));
+    let is_string = |cell: &DataType| matches!(cell, DataType::String(_));
+
+    let mut used = BTreeSet::new();
+    let mut find = |matches: &dyn Fn(&DataType) -> bool| {
+        let col = (0..width)
+            .find(|col| !used.contains(col) && confidence(*col, matches) > INFER_CONFIDENCE)?;
+        used.insert(col);
+        Some(col)
+    };
+
+    let period = find(&is_date)?;
+    let tx_type = find(&is_tx_type)?;
+    let value = find(&is_number)?;
+    let category = find(&is_string)?;
+
+    Some(Columns {
+        period,
+        category,
+        tx_type,
+        value,
+    })
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ParseField {
+    Period,
+    Category,
+    TxType,
+    Value,
+}
+
+impl fmt::Display for ParseField {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseField::Period => write!(f, "Period"),
+            ParseField::Category => write!(f, "Category"),
+            ParseField::TxType => write!(f, "TxType"),
+            ParseField::Value => write!(f, "Value"),
         }
     }
 }
-*/
 
-pub fn parse_report(file: String, group_by: GroupBy) -> Result<Vec<WorksheetData>, MyCustomError> {
-    let mut workbook: Xlsx<_> = open_workbook(file)?;
-    let group_by_fn = period_from_date(group_by);
-        
-    workbook.worksheets()
-        .into_iter()
-        .map(|(name, range)| read_worksheet(name, range, group_by_fn))
-        .collect()
+#[derive(Debug, Clone)]
+pub struct RowParseError {
+    pub row_idx: usize,
+    pub field: ParseField,
+    pub raw_value: String,
+    pub reason: String,
 }
 
-fn worksheet_data_to_periods(data: &Vec<WorksheetData>) -> Vec<Period> {
-    let mut periods: BTreeSet<Period> = BTreeSet::new();
+impl fmt::Display for RowParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "row {}, field {}: {} '{}'",
+            self.row_idx, self.field, self.reason, self.raw_value
+        )
+    }
+}
 
-    for worksheet_data in data {
-        for (_cat, by_cat) in worksheet_data {
-            for (period, _by_period) in by_cat {
-                periods.insert(period.clone());
-            }
+fn raw_cell_value(cell: &DataType) -> String {
+    match cell {
+        DataType::String(s) => s.clone(),
+        DataType::Empty => String::new(),
+        _ => cell.to_string(),
+    }
+}
+
+fn period_cell(cell: &DataType) -> Option<NaiveDate> {
+    match cell {
+        DataType::String(s) => NaiveDate::parse_from_str(s, "%d.%m.%Y").ok(),
+        DataType::DateTime(serial) => date_from_serial(*serial),
+        _ => None,
+    }
+}
+
+fn value_cell(cell: &DataType) -> Option<f64> {
+    match cell {
+        DataType::Float(f) => Some(*f),
+        DataType::Int(i) => Some(*i as f64),
+        DataType::String(s) => s.trim().parse::<f64>().ok(),
+        DataType::Empty => Some(0.0),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RowDefaults {
+    pub period: NaiveDate,
+    pub category: Category,
+    pub tx_type: TxType,
+    pub value: f64,
+    pub labels: TxTypeLabels,
+}
+
+impl Default for RowDefaults {
+    fn default() -> Self {
+        RowDefaults {
+            period: NaiveDate::from_ymd(1970, 1, 1),
+            category: Category::from("Uncategorized"),
+            tx_type: TxType::Outcome,
+            value: 0.0,
+            labels: TxTypeLabels::default(),
         }
     }
+}
 
-    periods.into_iter().collect()
+fn is_empty_row(row: &[DataType]) -> bool {
+    row.iter().all(|cell| match cell {
+        DataType::Empty => true,
+        DataType::String(s) => s.trim().is_empty(),
+        _ => false,
+    })
 }
 
-fn y(by_cat: &BTreeMap<Period, f64>, periods: &Vec<Period>) -> Vec<f64> {
-    periods
-        .iter()
-        .map(|p| by_cat.get(p).unwrap_or(&0.0).clone())
-        .collect()
+pub fn read_row_lenient(columns: &Columns, row: &[DataType], defaults: &RowDefaults) -> Fields {
+    let cell = |col: usize| row.get(col).unwrap_or(&DataType::Empty);
+    let defaulted = |field: ParseField, col: usize| {
+        warn!(field = %field, raw_value = %raw_cell_value(cell(col)), "using default value");
+    };
+
+    let period = period_cell(cell(columns.period));
+    let period_defaulted = period.is_none();
+    let period = period.unwrap_or_else(|| {
+        defaulted(ParseField::Period, columns.period);
+        defaults.period
+    });
+    let category = match cell(columns.category) {
+        DataType::String(s) if !s.is_empty() => Category::from(s.as_str()),
+        _ => {
+            defaulted(ParseField::Category, columns.category);
+            defaults.category.clone()
+        }
+    };
+    let tx_type = match cell(columns.tx_type) {
+        DataType::String(s) => read_tx_type(s, &defaults.labels).ok(),
+        _ => None,
+    }
+    .unwrap_or_else(|| {
+        defaulted(ParseField::TxType, columns.tx_type);
+        defaults.tx_type
+    });
+    let value = value_cell(cell(columns.value)).unwrap_or_else(|| {
+        defaulted(ParseField::Value, columns.value);
+        defaults.value
+    });
+
+    Fields {
+        period,
+        category,
+        tx_type,
+        value,
+        period_defaulted,
+    }
 }
 
-fn is_spending_category(y_values: &Vec<f64>) -> bool {
-    median(y_values) > 0.0
+pub fn read_row_with_index(
+    columns: &Columns,
+    row: &[DataType],
+    row_idx: usize,
+    labels: &TxTypeLabels,
+) -> Result<Fields, RowParseError> {
+    let error = |field: ParseField, col: usize, reason: String| RowParseError {
+        row_idx,
+        field,
+        raw_value: row.get(col).map(raw_cell_value).unwrap_or_default(),
+        reason,
+    };
+
+    let period = period_cell(&row[columns.period]);
+    if period == None {
+        return Err(error(
+            ParseField::Period,
+            columns.period,
+            String::from("can't parse"),
+        ));
+    }
+
+    let mut category = None;
+    if let DataType::String(s) = &row[columns.category] {
+        category = Some(s);
+    }
+    if category == None {
+        return Err(error(
+            ParseField::Category,
+            columns.category,
+            String::from("can't read"),
+        ));
+    }
+
+    let mut tx_type = None;
+    if let DataType::String(s) = &row[columns.tx_type] {
+        tx_type = Some(
+            read_tx_type(s, labels)
+                .map_err(|e| error(ParseField::TxType, columns.tx_type, e.to_string()))?,
+        );
+    }
+    if tx_type.is_none() {
+        return Err(error(
+            ParseField::TxType,
+            columns.tx_type,
+            String::from("can't read"),
+        ));
+    }
+
+    if let DataType::Empty = &row[columns.value] {
+        debug!(row = row_idx, "empty value cell, treating as 0.0");
+    }
+    let value = value_cell(&row[columns.value]);
+    if value == None {
+        return Err(error(
+            ParseField::Value,
+            columns.value,
+            String::from("can't parse"),
+        ));
+    }
+
+    Ok(Fields {
+        period: period.unwrap(),
+        category: Category::from(category.unwrap().as_str()),
+        tx_type: tx_type.unwrap(),
+        value: value.unwrap(),
+        period_defaulted: false,
+    })
 }
 
-fn plot(title: String, worksheet_data: &WorksheetData, periods: &Vec<Period>) -> Plot {
-    let mut plot = Plot::new();
-    plot.set_layout(
-        Layout::new()
-            .title(Title::new(&fix_label(&title)))
-    );
+pub type WorksheetData = BTreeMap<Category, BTreeMap<Period, f64>>;
 
-    let mut y_total : Vec<f64> = Vec::new();
-    for _ in periods.iter() {
-        y_total.push(0.0);
+pub fn read_worksheet_from_range(
+    name: String,
+    range: Range<DataType>,
+    group_by: fn(NaiveDate) -> Period,
+    headers: ColumnHeaders,
+) -> Result<WorksheetData, MyCustomError> {
+    let options = ParseOptions {
+        column_headers: headers,
+        ..ParseOptions::default()
+    };
+    read_worksheet(name, range, &group_by, &options)
+}
+
+fn read_worksheet(
+    name: String,
+    range: Range<DataType>,
+    group_by: &(dyn Fn(NaiveDate) -> Period + Sync),
+    options: &ParseOptions,
+) -> Result<WorksheetData, MyCustomError> {
+    let (rows, currency) = read_worksheet_fields(&name, &range, options)?;
+    aggregate_worksheet(name, rows, currency, group_by, options)
+}
+
+fn aggregate_worksheet(
+    name: String,
+    mut rows: Vec<Fields>,
+    currency: Option<&'static str>,
+    group_by: &(dyn Fn(NaiveDate) -> Period + Sync),
+    options: &ParseOptions,
+) -> Result<WorksheetData, MyCustomError> {
+    if !options.include_transfers {
+        rows.retain(|fields| fields.tx_type != TxType::Transfer);
     }
 
-    for (cat, by_cat) in worksheet_data {
-        let y_values = y(by_cat, &periods);
-        if is_spending_category(&y_values) {
-            for it in y_values.iter().zip(y_total.iter_mut()) {
-                let (v, t) = it;
-                *t += *v;
+    let mut by_category = aggregate_fields(&rows, group_by);
+
+    if let (Some(conversion), Some(currency)) = (&options.currency_conversion, currency) {
+        by_category =
+            normalize_currency(by_category, &conversion.rates, currency, &conversion.target)?;
+    }
+
+    info!(sheet = %name, categories = by_category.len(), "worksheet processed");
+    Ok(by_category)
+}
+
+struct HeaderPositions {
+    period_pos: Option<usize>,
+    category_pos: Option<usize>,
+    tx_type_pos: Option<usize>,
+    value_pos: Option<usize>,
+    currency: Option<&'static str>,
+}
+
+#[cfg(feature = "streaming")]
+impl HeaderPositions {
+    fn columns(&self) -> Option<Columns> {
+        Some(Columns {
+            period: self.period_pos?,
+            category: self.category_pos?,
+            tx_type: self.tx_type_pos?,
+            value: self.value_pos?,
+        })
+    }
+}
+
+fn find_header_positions(first_row: &[DataType], headers: &ColumnHeaders) -> HeaderPositions {
+    let period_dt = DataType::String(headers.period.clone());
+    let category_dt = DataType::String(headers.category.clone());
+    let tx_type_dt = DataType::String(headers.tx_type.clone());
+    let value_dt = DataType::String(headers.value.clone());
+
+    let mut positions = HeaderPositions {
+        period_pos: None,
+        category_pos: None,
+        tx_type_pos: None,
+        value_pos: None,
+        currency: None,
+    };
+
+    for i in 0..first_row.len() {
+        if first_row[i] == period_dt {
+            positions.period_pos = Some(i);
+        } else if first_row[i] == category_dt {
+            positions.category_pos = Some(i);
+        } else if first_row[i] == tx_type_dt {
+            positions.tx_type_pos = Some(i);
+        } else if first_row[i] == value_dt {
+            positions.value_pos = Some(i);
+            positions.currency = currency_code(&headers.value);
+        } else if let DataType::String(header) = &first_row[i] {
+            if positions.value_pos == None {
+                if let Some(code) = currency_code(header) {
+                    positions.value_pos = Some(i);
+                    positions.currency = Some(code);
+                }
             }
-            let label = format!("{} (avg: {}k)", cat, (mean(&y_values) as i32) / 1000);
-            plot.add_trace(
-                Scatter::new(periods.to_owned(), y_values.to_owned())
-                    .name(&fix_label(&label))
-                    .mode(Mode::LinesMarkers)
-                    .line(Line::new()),
-            );
         }
     }
 
-    let label = format!("Всего (avg: {}k)", (mean(&y_total) as i32) / 1000);
-    plot.add_trace(
-        Scatter::new(periods.to_owned(), y_total.to_owned())
-            .name(&fix_label(&label))
-            .mode(Mode::LinesMarkers)
-            .line(Line::new()),
-    );
-
-    plot
+    positions
 }
 
-pub fn draw(data: Vec<WorksheetData>) {
-    let periods = last_n_groups(worksheet_data_to_periods(&data), MAX_PERIODS);
+fn read_worksheet_fields(
+    name: &str,
+    range: &Range<DataType>,
+    options: &ParseOptions,
+) -> Result<(Vec<Fields>, Option<&'static str>), MyCustomError> {
+    let headers = &options.column_headers;
 
-    for worksheet_data in data {
-        let title = String::from("Все траты");
-        let plot = plot(title, &worksheet_data, &periods);
-        plot.show();
+    let first_row = match range.rows().nth(options.skip_rows) {
+        Some(first_row) => first_row,
+        None => {
+            //return Err(format!("Can't read first row from sheet '{}'", name));
+            return Err(MyCustomError::OtherError)
+        }
+    };
+
+    let HeaderPositions {
+        mut period_pos,
+        mut category_pos,
+        mut tx_type_pos,
+        mut value_pos,
+        currency,
+    } = find_header_positions(first_row, headers);
+
+    if !options.strict_headers
+        && (period_pos == None || category_pos == None || tx_type_pos == None || value_pos == None)
+    {
+        if let Some(columns) = infer_columns_heuristically(range, options) {
+            warn!(sheet = %name, columns = ?columns, "headers not found, using inferred columns");
+            period_pos = Some(columns.period);
+            category_pos = Some(columns.category);
+            tx_type_pos = Some(columns.tx_type);
+            value_pos = Some(columns.value);
+        }
+    }
+
+    if period_pos == None {
+        return Err(MyCustomError::OtherError)
+//        return Err(format!(
+//            "Can't find column '{}' in sheet '{}'",
+//            headers.period, name
+//        ));
+    }
+    if category_pos == None {
+        return Err(MyCustomError::OtherError)
+//        return Err(format!(
+//            "Can't find column '{}' in sheet '{}'",
+//            headers.category, name
+//        ));
+    }
+    if tx_type_pos == None {
+        return Err(MyCustomError::OtherError)
+//        return Err(format!(
+//            "Can't find column '{}' in sheet '{}'",
+//            headers.tx_type, name
+//        ));
+    }
+    if value_pos == None {
+        return Err(MyCustomError::OtherError)
+//        return Err(format!(
+//            "Can't fund column '{}' in sheet '{}'",
+//            headers.value, name
+//        ));
+    }
+
+    let columns = Columns {
+        period: period_pos.unwrap(),
+        category: category_pos.unwrap(),
+        tx_type: tx_type_pos.unwrap(),
+        value: value_pos.unwrap(),
+    };
+
+    let mut rows = Vec::new();
+    if options.lenient {
+        let defaults = RowDefaults {
+            labels: options.tx_type_labels.clone(),
+            ..RowDefaults::default()
+        };
+        for (row_idx, row) in range.rows().enumerate().skip(options.skip_rows + 1) {
+            if is_empty_row(row) {
+                continue;
+            }
+            let fields = read_row_lenient(&columns, row, &defaults);
+            if fields.period_defaulted {
+                warn!(sheet = %name, row = row_idx, period = %fields.period, "period defaulted");
+            }
+            rows.push(fields);
+        }
+    } else {
+        for (row_idx, row) in range.rows().enumerate().skip(options.skip_rows + 1) {
+            match read_row_with_index(&columns, row, row_idx, &options.tx_type_labels) {
+                Ok(fields) => rows.push(fields),
+                Err(e) => debug!(sheet = %name, error = %e, "skipping row"),
+            }
+        }
+    }
+
+    if options.warn_duplicates || options.deduplicate || options.strict_duplicates {
+        let groups = detect_duplicate_transactions(&rows);
+        let duplicates: usize = groups.iter().map(|group| group.count - 1).sum();
+        if options.warn_duplicates {
+            for group in groups.iter().filter(|group| group.count > 1) {
+                warn!(
+                    sheet = %name,
+                    count = group.count,
+                    row = %group.fields,
+                    "duplicate transaction"
+                );
+            }
+        }
+        if options.strict_duplicates && duplicates > 0 {
+            return Err(MyCustomError::DuplicatesFound(duplicates));
+        }
+        if options.deduplicate {
+            rows = groups.into_iter().map(|group| group.fields).collect();
+        }
+    }
+
+    Ok((rows, currency))
+}
+
+fn aggregate_fields(
+    fields: &[Fields],
+    group_by: &(dyn Fn(NaiveDate) -> Period + Sync),
+) -> WorksheetData {
+    let mut by_category: WorksheetData = BTreeMap::new();
+
+    for fields in fields {
+        add_fields(&mut by_category, fields, group_by);
+    }
+
+    by_category
+}
+
+fn add_fields(
+    by_category: &mut WorksheetData,
+    fields: &Fields,
+    group_by: &(dyn Fn(NaiveDate) -> Period + Sync),
+) {
+    let period = group_by(fields.period);
+
+    let addition = match fields.tx_type {
+        TxType::Outcome => fields.value,
+        TxType::Income => -fields.value,
+        TxType::Transfer => 0.0,
+    };
+
+    *by_category
+        .entry(fields.category.clone())
+        .or_insert(BTreeMap::new())
+        .entry(period)
+        .or_insert(0.0) += addition;
+}
+
+pub fn fields_to_worksheet_data(fields: &[Fields], group_by: fn(NaiveDate) -> Period) -> WorksheetData {
+    aggregate_fields(fields, &group_by)
+}
+
+pub fn read_transaction_list(
+    file: String,
+    options: &ParseOptions,
+) -> Result<Vec<Fields>, MyCustomError> {
+    let mut workbook: Xlsx<_> = open_workbook(file)?;
+
+    let mut worksheets = workbook.worksheets();
+    if let Some(filter) = &options.sheet_filter {
+        worksheets.retain(|(name, _range)| filter.is_match(name));
+    }
+
+    let mut result = Vec::new();
+    for (name, range) in worksheets {
+        let (mut rows, _currency) = read_worksheet_fields(&name, &range, options)?;
+        if !options.include_transfers {
+            rows.retain(|fields| fields.tx_type != TxType::Transfer);
+        }
+        debug!(sheet = %name, rows = rows.len(), "transactions read");
+        result.extend(rows);
+    }
+    Ok(result)
+}
+
+#[derive(Debug, Clone)]
+pub struct DuplicateGroup {
+    pub fields: Fields,
+    pub count: usize,
+}
+
+pub fn detect_duplicate_transactions(data_raw: &[Fields]) -> Vec<DuplicateGroup> {
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    let mut index = HashMap::new();
+
+    for fields in data_raw {
+        let key = (
+            fields.period,
+            fields.category.clone(),
+            fields.tx_type,
+            fields.value.to_bits(),
+        );
+        match index.get(&key) {
+            Some(i) => groups[*i].count += 1,
+            None => {
+                index.insert(key, groups.len());
+                groups.push(DuplicateGroup {
+                    fields: fields.clone(),
+                    count: 1,
+                });
+            }
+        }
+    }
+
+    groups
+}
+
+fn currency_code(header: &str) -> Option<&'static str> {
+    header
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .find_map(|word| {
+            CURRENCY_CODES
+                .iter()
+                .find(|code| code.eq_ignore_ascii_case(word))
+                .copied()
+        })
+}
+
+pub fn read_currency_map(file: &str) -> Result<CurrencyMap, MyCustomError> {
+    let mut reader = ReaderBuilder::new().trim(csv::Trim::All).from_path(file)?;
+    let mut rates = CurrencyMap::default();
+
+    for record in reader.deserialize() {
+        let (period, from, to, rate): (String, String, String, f64) =
+            record.map_err(|e| MyCustomError::InvalidConfig(e.to_string()))?;
+        rates.insert(Period::from(period), &from, &to, rate);
+    }
+
+    Ok(rates)
+}
+
+pub fn normalize_currency(
+    data: WorksheetData,
+    rates: &CurrencyMap,
+    from: &str,
+    target: &str,
+) -> Result<WorksheetData, MyCustomError> {
+    let mut result: WorksheetData = BTreeMap::new();
+
+    for (cat, by_cat) in data {
+        let mut converted = BTreeMap::new();
+        for (period, value) in by_cat {
+            let rate = rates.rate(&period, from, target).ok_or_else(|| {
+                MyCustomError::MissingExchangeRate {
+                    currency: from.to_string(),
+                    period: period.to_string(),
+                }
+            })?;
+            converted.insert(period, value * rate);
+        }
+        result.insert(cat, converted);
+    }
+
+    Ok(result)
+}
+
+pub fn last_n_groups(periods: Vec<Period>, n: usize) -> Vec<Period> {
+    periods
+        .into_iter()
+        .rev()
+        .take(n + 1)
+        .skip(1)
+        .rev()
+        .collect()
+}
+
+pub fn last_n_groups_before(periods: Vec<Period>, n: usize, anchor: &Period) -> Vec<Period> {
+    match periods.iter().position(|p| p == anchor) {
+        Some(pos) => {
+            let start = (pos + 1).saturating_sub(n);
+            periods[start..=pos].to_vec()
+        }
+        None => Vec::new(),
+    }
+}
+
+/*
+#[derive(Debug, Clone)]
+struct ChartData {
+    name: String,
+    y_values: Vec<f64>,
+    line: Line,
+}
+
+#[derive(Debug, Clone)]
+struct ImageData {
+    x_values: Vec<Period>,
+    charts: Vec<ChartData>,
+}
+
+fn worksheet_data_to_image_data(worksheet_data: WorksheetData, periods: Vec<Period>) -> ImageData {
+    let mut chart_data = Vec::new();
+
+    for (category, by_period) in &worksheet_data {
+        let y_values: Vec<_> = periods
+            .iter()
+            .map(|period| match by_period.get(period) {
+                Some(v) => *v,
+                None => 0.0,
+            })
+            .collect();
+
+        chart_data.push(ChartData {
+            name: category.to_string(),
+            y_values: y_values,
+            line: Line::new(),
+        })
+    }
+
+    ImageData {
+        x_values: periods,
+        charts: chart_data,
+    }
+}
+
+fn filter_all_spendings(data: Vec<ChartData>) -> Vec<ChartData> {
+    data.into_iter()
+        .filter(|cd| median(&cd.y_values) > 0.0)
+        .collect()
+}
+
+fn filter_regular_spendings(data: Vec<ChartData>) -> Vec<ChartData> {
+    data.into_iter()
+        .filter(|cd| {
+            let mean = mean(&cd.y_values);
+            let median = median(&cd.y_values);
+            (median > 0.0) && (mean < 2.0 * median) && (median < 2.0 * mean)
+        })
+        .collect()
+}
+
+*/
+
+/*
+fn add_total(mut image_data: ImageData) -> ImageData {
+    let len = image_data.x_values.len();
+
+    let mut total: Vec<f64> = vec![0.0; len];
+    for chart in &image_data.charts {
+        for i in 0..len {
+            total[i] += chart.y_values[i];
+        }
+    }
+
+    image_data.charts.push(ChartData {
+        name: String::from("Всего"),
+        y_values: total,
+        line: Line::new().dash(DashType::LongDashDot),
+    });
+
+    image_data
+}
+*/
+
+fn fix_label(s: &str) -> String {
+    s.replace(" ", "&nbsp;")
+}
+
+fn generate_random_filename() -> String {
+    Uuid::new_v4().to_string()
+}
+
+pub fn generate_output_path(dir: &Path, suffix: &str) -> PathBuf {
+    let mut filename = generate_random_filename();
+    filename.push_str(suffix);
+    dir.join(filename)
+}
+
+fn sheet_output_path(path: &Path, sheet: usize) -> PathBuf {
+    if sheet == 0 {
+        return path.to_path_buf();
+    }
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("chart");
+    let filename = match path.extension().and_then(|s| s.to_str()) {
+        Some(ext) => format!("{}-{}.{}", stem, sheet, ext),
+        None => format!("{}-{}", stem, sheet),
+    };
+    path.with_file_name(filename)
+}
+
+pub fn save_plot_html(plot: &mut Plot, path: &Path) -> Result<(), MyCustomError> {
+    plot.use_local_plotly();
+    fs::write(path, plot.to_html()).map_err(|e| MyCustomError::HtmlExportError(e.to_string()))
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum OutputFormat {
+    Browser,
+    PlotlyHtml,
+    Ndjson,
+    Gnuplot,
+    Prometheus,
+    Text,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "browser" => Ok(OutputFormat::Browser),
+            "plotly-html" => Ok(OutputFormat::PlotlyHtml),
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "gnuplot" => Ok(OutputFormat::Gnuplot),
+            "prometheus" => Ok(OutputFormat::Prometheus),
+            "text" => Ok(OutputFormat::Text),
+            _ => Err(format!("Unknown output format '{}'", s)),
+        }
+    }
+}
+
+/*
+fn draw_image(
+    image_data: &ImageData,
+    title: String,
+    filter: fn(Vec<ChartData>) -> Vec<ChartData>,
+) -> Result<PathBuf, String> {
+    let filtered_image_data = ImageData {
+        x_values: image_data.x_values.clone(),
+        charts: filter(image_data.charts.clone()),
+    };
+    let filtered_image_data = add_total(filtered_image_data);
+
+    let mut plot = Plot::new();
+    let layout = Layout::new().title(Title::new(&fix_label(&title)));
+    plot.set_layout(layout);
+
+    for data in filtered_image_data.charts {
+        let mean = mean(&data.y_values);
+        let label = format!("{} (avg: {}k)", data.name, (mean as i32) / 1000);
+        plot.add_trace(
+            Scatter::new(image_data.x_values.to_owned(), data.y_values.to_owned())
+                .name(&fix_label(&label))
+                .mode(Mode::LinesMarkers)
+                .line(data.line),
+        );
+    }
+
+    
+    let path = generate_output_path(Path::new("/tmp"), ".svg");
+
+    //plot.save(&path, ImageFormat::SVG, config.width as usize, config.height as usize, 1.0);
+    plot.show();
+    Ok(path)
+}
+*/
+
+/*
+pub fn parse_report_and_draw_images(file: String, group: GroupBy) -> Result<Vec<PathBuf>, String> {
+    let group_by = period_from_date(group);
+    let maybe_workbook: Result<Xlsx<_>, XlsxError> = open_workbook(file);
+    match maybe_workbook {
+        Err(e) => Err(e.to_string()),
+        Ok(mut workbook) => {
+            let mut result: Vec<Result<PathBuf, String>> = Vec::new();
+
+            for (worksheet_name, range) in workbook.worksheets() {
+                if let Ok(worksheet_data)) =
+                    read_worksheet(worksheet_name, range, group_by)
+                {
+                    let x_values: Vec<_> = last_n_groups(periods, MAX_PERIODS);
+                    let image_data = worksheet_data_to_image_data(worksheet_data, x_values);
+
+                    result.push(draw_image(
+                        &image_data,
+                        String::from("Регулярные траты"),
+                        filter_regular_spendings,
+                    ));
+                    result.push(draw_image(
+                        &image_data,
+                        String::from("Все траты"),
+                        filter_all_spendings,
+                    ));
+                }
+            }
+
+            let (results, errors): (Vec<_>, Vec<_>) = result.into_iter().partition(|r| r.is_ok());
+
+            let errors: Vec<_> = errors.into_iter().map(Result::unwrap_err).collect();
+
+            if results.len() == 0 {
+                Err(String::from("Not found sheets with report"))
+            } else if errors.len() > 0 {
+                Err(errors.join(";\n"))
+            } else {
+                Ok(results.into_iter().map(Result::unwrap).collect())
+            }
+        }
+    }
+}
+*/
+
+pub fn list_sheets(file: &str) -> Result<Vec<String>, MyCustomError> {
+    let workbook: Xlsx<_> = open_workbook(file)?;
+    Ok(workbook.sheet_names().to_owned())
+}
+
+#[derive(Debug, Clone)]
+pub struct SheetMetadata {
+    pub name: String,
+    pub rows: usize,
+    pub headers: Vec<String>,
+    pub first_date: Option<NaiveDate>,
+    pub last_date: Option<NaiveDate>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkbookMetadata {
+    pub sheets: Vec<SheetMetadata>,
+}
+
+pub fn read_workbook_metadata(file: &str) -> Result<WorkbookMetadata, MyCustomError> {
+    let mut workbook: Xlsx<_> = open_workbook(file)?;
+    let period_header = ColumnHeaders::default().period;
+
+    let sheets = workbook
+        .worksheets()
+        .into_iter()
+        .map(|(name, range)| {
+            let headers: Vec<String> = range
+                .rows()
+                .next()
+                .map(|row| row.iter().map(raw_cell_value).collect())
+                .unwrap_or_default();
+            let rows = range.height().saturating_sub(1);
+            let date_col = headers.iter().position(|header| *header == period_header);
+            let dates: Vec<NaiveDate> = match date_col {
+                Some(col) => range
+                    .rows()
+                    .skip(1)
+                    .filter_map(|row| row.get(col).and_then(period_cell))
+                    .collect(),
+                None => Vec::new(),
+            };
+            SheetMetadata {
+                name,
+                rows,
+                headers,
+                first_date: dates.first().cloned(),
+                last_date: dates.last().cloned(),
+            }
+        })
+        .collect();
+
+    Ok(WorkbookMetadata { sheets })
+}
+
+pub fn parse_report(
+    file: String,
+    group_by: GroupBy,
+    options: &ParseOptions,
+) -> Result<Vec<WorksheetData>, MyCustomError> {
+    let mut workbook: Xlsx<_> = open_workbook(file)?;
+    let rolling = group_by == GroupBy::RollingQuarter;
+    let group_by_fn = if rolling {
+        period_from_date(GroupBy::Month)
+    } else {
+        period_from_date(group_by)
+    };
+
+    let mut worksheets = workbook.worksheets();
+    if let Some(filter) = &options.sheet_filter {
+        worksheets.retain(|(name, _range)| filter.is_match(name));
+    }
+
+    #[cfg(feature = "parallel")]
+    let worksheets = worksheets.into_par_iter();
+    #[cfg(not(feature = "parallel"))]
+    let worksheets = worksheets.into_iter();
+
+    worksheets
+        .map(|(name, range)| {
+            read_worksheet(name, range, &*group_by_fn, options)
+                .map(|data| if rolling { rolling_quarter_sums(data) } else { data })
+        })
+        .collect()
+}
+
+#[cfg(feature = "streaming")]
+pub fn read_worksheet_streaming(
+    path: &Path,
+    sheet_name: &str,
+    group_by: fn(NaiveDate) -> Period,
+) -> Result<WorksheetData, MyCustomError> {
+    let options = ParseOptions::default();
+    let mut header: Option<(Columns, usize)> = None;
+    let mut by_category = WorksheetData::new();
+
+    streaming::for_each_row(path, sheet_name, |row_idx, mut row| {
+        let (columns, width) = match header {
+            Some(header) => header,
+            None => {
+                let columns = find_header_positions(&row, &options.column_headers)
+                    .columns()
+                    .ok_or(MyCustomError::OtherError)?;
+                header = Some((columns, row.len()));
+                return Ok(());
+            }
+        };
+
+        if row.len() < width {
+            row.resize(width, DataType::Empty);
+        }
+        match read_row_with_index(&columns, &row, row_idx, &options.tx_type_labels) {
+            Ok(fields) if fields.tx_type == TxType::Transfer && !options.include_transfers => {}
+            Ok(fields) => add_fields(&mut by_category, &fields, &group_by),
+            Err(e) => debug!(sheet = %sheet_name, error = %e, "skipping row"),
+        }
+        Ok(())
+    })?;
+
+    if header.is_none() {
+        return Err(MyCustomError::OtherError);
+    }
+
+    info!(sheet = %sheet_name, categories = by_category.len(), "worksheet processed");
+    Ok(by_category)
+}
+
+pub fn parse_report_with_inferred_grouping(
+    file: String,
+    options: &ParseOptions,
+) -> Result<(Vec<WorksheetData>, GroupBy), MyCustomError> {
+    let mut workbook: Xlsx<_> = open_workbook(file)?;
+
+    let mut worksheets = workbook.worksheets();
+    if let Some(filter) = &options.sheet_filter {
+        worksheets.retain(|(name, _range)| filter.is_match(name));
+    }
+
+    let mut sheets = Vec::new();
+    for (name, range) in worksheets {
+        let (rows, currency) = read_worksheet_fields(&name, &range, options)?;
+        sheets.push((name, rows, currency));
+    }
+
+    let dates: Vec<NaiveDate> = sheets
+        .iter()
+        .flat_map(|(_name, rows, _currency)| rows.iter().map(|fields| fields.period))
+        .collect();
+    let group_by = match infer_group_by_from_dates(&dates) {
+        Some(group_by) => {
+            info!(group_by = %group_by, "detected grouping");
+            group_by
+        }
+        None => {
+            warn!("no transactions to infer grouping from, using month");
+            GroupBy::Month
+        }
+    };
+    let group_by_fn = period_from_date(group_by.clone());
+
+    let data = sheets
+        .into_iter()
+        .map(|(name, rows, currency)| {
+            aggregate_worksheet(name, rows, currency, &*group_by_fn, options)
+        })
+        .collect::<Result<_, _>>()?;
+    Ok((data, group_by))
+}
+
+pub fn parse_report_with_custom_grouping(
+    file: String,
+    group_by: impl Fn(NaiveDate) -> Period + Send + Sync + 'static,
+    options: &ParseOptions,
+) -> Result<Vec<WorksheetData>, MyCustomError> {
+    parse_report(file, GroupBy::Custom(Arc::new(group_by)), options)
+}
+
+#[cfg(feature = "parallel")]
+pub fn read_worksheet_parallel(
+    worksheets: Vec<(String, Range<DataType>)>,
+    group_by: fn(NaiveDate) -> Period,
+) -> Vec<Result<WorksheetData, MyCustomError>> {
+    let options = ParseOptions::default();
+    worksheets
+        .into_par_iter()
+        .map(|(name, range)| read_worksheet(name, range, &group_by, &options))
+        .collect()
+}
+
+fn rename_categories(data: WorksheetData, rename: impl Fn(Category) -> Category) -> WorksheetData {
+    let mut result: WorksheetData = BTreeMap::new();
+
+    for (cat, by_cat) in data {
+        let entry = result.entry(rename(cat)).or_insert(BTreeMap::new());
+        for (period, value) in by_cat {
+            *entry.entry(period).or_insert(0.0) += value;
+        }
+    }
+
+    result
+}
+
+#[derive(Debug, Clone)]
+pub struct RepairNote {
+    pub category: Category,
+    pub tx_type: TxType,
+    pub negative_values: usize,
+    pub total_values: usize,
+}
+
+pub fn repair_worksheet_data(data: WorksheetData) -> (WorksheetData, Vec<RepairNote>) {
+    let mut notes = Vec::new();
+    let repaired = data
+        .into_iter()
+        .map(|(cat, by_cat)| {
+            let negative_values = by_cat.values().filter(|value| **value < 0.0).count();
+            if negative_values * 2 <= by_cat.len() {
+                return (cat, by_cat);
+            }
+            notes.push(RepairNote {
+                category: cat.clone(),
+                tx_type: TxType::Income,
+                negative_values,
+                total_values: by_cat.len(),
+            });
+            let flipped = by_cat.into_iter().map(|(period, value)| (period, -value)).collect();
+            (cat, flipped)
+        })
+        .collect();
+    (repaired, notes)
+}
+
+pub fn apply_aliases(data: WorksheetData, aliases: &HashMap<String, String>) -> WorksheetData {
+    rename_categories(data, |cat| match aliases.get(cat.as_str()) {
+        Some(canonical) => Category::from(canonical.as_str()),
+        None => cat,
+    })
+}
+
+pub fn scale_worksheet_data(data: WorksheetData, factor: f64) -> WorksheetData {
+    data.into_iter()
+        .map(|(cat, by_cat)| {
+            let scaled = by_cat.into_iter().map(|(period, value)| (period, value * factor)).collect();
+            (cat, scaled)
+        })
+        .collect()
+}
+
+pub fn filter_by_value_range(data: WorksheetData, min_value: f64, max_value: f64) -> WorksheetData {
+    data.into_iter()
+        .filter_map(|(cat, by_cat)| {
+            let kept: BTreeMap<Period, f64> = by_cat
+                .into_iter()
+                .filter(|(period, value)| {
+                    let in_range = *value >= min_value && *value <= max_value;
+                    if !in_range {
+                        warn!(
+                            category = %cat,
+                            period = %period,
+                            value = value,
+                            "value out of range, dropping"
+                        );
+                    }
+                    in_range
+                })
+                .collect();
+            if kept.is_empty() {
+                None
+            } else {
+                Some((cat, kept))
+            }
+        })
+        .collect()
+}
+
+pub fn group_categories_by_tag(
+    aliases_config: &Config,
+    data: WorksheetData,
+) -> BTreeMap<String, WorksheetData> {
+    aliases_config
+        .tags
+        .iter()
+        .map(|(tag, categories)| {
+            let tagged = categories
+                .iter()
+                .filter_map(|cat| data.get_key_value(cat.as_str()))
+                .map(|(cat, by_cat)| (cat.clone(), by_cat.clone()))
+                .collect();
+            (tag.clone(), tagged)
+        })
+        .collect()
+}
+
+pub fn tag_totals(by_tag: BTreeMap<String, WorksheetData>) -> WorksheetData {
+    by_tag
+        .into_iter()
+        .map(|(tag, tagged)| {
+            let mut total = BTreeMap::new();
+            for (period, value) in tagged.into_values().flatten() {
+                *total.entry(period).or_insert(0.0) += value;
+            }
+            (Category::from(tag), total)
+        })
+        .collect()
+}
+
+pub fn trim_category_names(data: WorksheetData) -> WorksheetData {
+    rename_categories(data, |cat| Category::from(cat.trim()))
+}
+
+pub fn lowercase_category_names(data: WorksheetData) -> WorksheetData {
+    rename_categories(data, |cat| Category::from(cat.to_lowercase()))
+}
+
+pub fn merge_worksheet_data(sheets: Vec<WorksheetData>) -> WorksheetData {
+    let mut result: WorksheetData = BTreeMap::new();
+
+    for worksheet_data in sheets {
+        for (cat, by_cat) in worksheet_data {
+            let entry = result.entry(cat).or_insert(BTreeMap::new());
+            for (period, value) in by_cat {
+                *entry.entry(period).or_insert(0.0) += value;
+            }
+        }
+    }
+
+    result
+}
+
+pub fn remove_empty_periods(data: &mut WorksheetData, periods: &mut Vec<Period>) {
+    periods.retain(|period| {
+        let values: Vec<f64> = data
+            .values()
+            .filter_map(|by_cat| by_cat.get(period))
+            .cloned()
+            .collect();
+        !values.is_empty() && values.iter().sum::<f64>() != 0.0
+    });
+
+    for by_cat in data.values_mut() {
+        by_cat.retain(|period, _| periods.contains(period));
+    }
+}
+
+pub fn read_period_groups(file: &str) -> Result<BTreeMap<String, Vec<Period>>, MyCustomError> {
+    let content = fs::read_to_string(file)
+        .map_err(|e| MyCustomError::ConfigError(e.to_string()))?;
+    let groups: BTreeMap<String, Vec<String>> =
+        serde_json::from_str(&content).map_err(|e| MyCustomError::ConfigError(e.to_string()))?;
+    Ok(groups
+        .into_iter()
+        .map(|(name, periods)| (name, periods.into_iter().map(Period::from).collect()))
+        .collect())
+}
+
+pub fn aggregate_by_custom_periods(
+    data: &WorksheetData,
+    period_groups: &BTreeMap<String, Vec<Period>>,
+) -> WorksheetData {
+    data.iter()
+        .map(|(cat, by_cat)| {
+            let by_group = period_groups
+                .iter()
+                .filter_map(|(name, periods)| {
+                    let values: Vec<f64> =
+                        periods.iter().filter_map(|p| by_cat.get(p)).cloned().collect();
+                    if values.is_empty() {
+                        None
+                    } else {
+                        Some((Period::from(name.as_str()), values.iter().sum::<f64>()))
+                    }
+                })
+                .collect();
+            (cat.clone(), by_group)
+        })
+        .collect()
+}
+
+pub fn merge_small_categories(
+    data: WorksheetData,
+    periods: &[Period],
+    threshold_pct: f64,
+) -> WorksheetData {
+    let totals: BTreeMap<Category, f64> = data
+        .iter()
+        .map(|(cat, by_cat)| (cat.clone(), y(by_cat, periods).iter().sum::<f64>()))
+        .collect();
+    let total_spending: f64 = totals.values().filter(|total| **total > 0.0).sum();
+    if total_spending == 0.0 {
+        return data;
+    }
+
+    rename_categories(data, |cat| {
+        let total = totals[&cat];
+        if total > 0.0 && total / total_spending * 100.0 < threshold_pct {
+            Category::from("Other")
+        } else {
+            cat
+        }
+    })
+}
+
+pub fn impute_missing_categories(
+    mut data: WorksheetData,
+    canonical_categories: &[Category],
+    periods: &[Period],
+) -> WorksheetData {
+    for cat in canonical_categories {
+        let by_cat = data.entry(cat.clone()).or_insert(BTreeMap::new());
+        for period in periods {
+            by_cat.entry(period.clone()).or_insert(0.0);
+        }
+    }
+    data
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HierarchicalNode {
+    pub value: BTreeMap<Period, f64>,
+    pub children: BTreeMap<String, HierarchicalNode>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HierarchicalData {
+    pub root: BTreeMap<String, HierarchicalNode>,
+}
+
+pub fn hierarchical_categories(data: WorksheetData, separator: char) -> HierarchicalData {
+    let mut result = HierarchicalData::default();
+
+    for (cat, by_cat) in data {
+        let mut level = &mut result.root;
+        for part in cat.split(separator).map(str::trim) {
+            let node = level.entry(part.to_string()).or_insert_with(HierarchicalNode::default);
+            for (period, value) in &by_cat {
+                *node.value.entry(period.clone()).or_insert(0.0) += value;
+            }
+            level = &mut node.children;
+        }
+    }
+
+    result
+}
+
+fn flatten_nodes(
+    nodes: &BTreeMap<String, HierarchicalNode>,
+    prefix: Option<&str>,
+    separator: char,
+    result: &mut WorksheetData,
+) {
+    for (name, node) in nodes {
+        let path = match prefix {
+            Some(prefix) => format!("{}{}{}", prefix, separator, name),
+            None => name.clone(),
+        };
+        flatten_nodes(&node.children, Some(&path), separator, result);
+        result.insert(Category::from(path), node.value.clone());
+    }
+}
+
+pub fn flatten_hierarchy(data: &HierarchicalData, separator: char) -> WorksheetData {
+    let mut result = BTreeMap::new();
+    flatten_nodes(&data.root, None, separator, &mut result);
+    result
+}
+
+pub fn diff_worksheet_data(
+    before: &WorksheetData,
+    after: &WorksheetData,
+    periods: &[Period],
+) -> WorksheetData {
+    let categories: BTreeSet<&Category> = before.keys().chain(after.keys()).collect();
+    let mut result: WorksheetData = BTreeMap::new();
+
+    for cat in categories {
+        let mut by_period = BTreeMap::new();
+        for period in periods {
+            let value = |data: &WorksheetData| {
+                data.get(cat)
+                    .and_then(|by_cat| by_cat.get(period))
+                    .cloned()
+                    .unwrap_or(0.0)
+            };
+            let delta = value(after) - value(before);
+            if delta != 0.0 {
+                by_period.insert(period.clone(), delta);
+            }
+        }
+        if !by_period.is_empty() {
+            result.insert(cat.clone(), by_period);
+        }
+    }
+
+    result
+}
+
+#[derive(Debug, Clone)]
+pub struct CategoryDiff {
+    pub category: Category,
+    pub before: f64,
+    pub after: f64,
+    pub change_pct: f64,
+}
+
+pub fn diff_category_totals(
+    before: &WorksheetData,
+    after: &WorksheetData,
+    periods: &[Period],
+) -> Vec<CategoryDiff> {
+    let categories: BTreeSet<&Category> = before.keys().chain(after.keys()).collect();
+    let total = |data: &WorksheetData, cat: &Category| match data.get(cat) {
+        Some(by_cat) => y(by_cat, periods).iter().sum::<f64>(),
+        None => 0.0,
+    };
+
+    categories
+        .into_iter()
+        .map(|cat| {
+            let (before_total, after_total) = (total(before, cat), total(after, cat));
+            let change_pct = match (before.contains_key(cat), after.contains_key(cat)) {
+                (false, _) => 100.0,
+                (_, false) => -100.0,
+                _ if before_total == 0.0 => f64::NAN,
+                _ => (after_total - before_total) / before_total.abs() * 100.0,
+            };
+            CategoryDiff {
+                category: cat.clone(),
+                before: before_total,
+                after: after_total,
+                change_pct,
+            }
+        })
+        .collect()
+}
+
+#[cfg(feature = "ndarray")]
+pub fn sparse_to_dense(
+    data: &WorksheetData,
+    periods: &[Period],
+) -> (Array2<f64>, Vec<Category>, Vec<Period>) {
+    let categories: Vec<Category> = data.keys().cloned().collect();
+    let mut matrix = Array2::zeros((categories.len(), periods.len()));
+    for (i, by_cat) in data.values().enumerate() {
+        for (j, value) in y(by_cat, periods).into_iter().enumerate() {
+            matrix[[i, j]] = value;
+        }
+    }
+    (matrix, categories, periods.to_vec())
+}
+
+#[cfg(feature = "ndarray")]
+pub fn dense_to_sparse(
+    matrix: &Array2<f64>,
+    categories: &[Category],
+    periods: &[Period],
+) -> WorksheetData {
+    categories
+        .iter()
+        .zip(matrix.rows())
+        .map(|(cat, row)| {
+            let by_cat = periods
+                .iter()
+                .zip(row.iter())
+                .filter(|(_period, value)| **value != 0.0)
+                .map(|(period, value)| (period.clone(), *value))
+                .collect();
+            (cat.clone(), by_cat)
+        })
+        .collect()
+}
+
+pub fn normalize_to_percent(data: &WorksheetData, periods: &[Period]) -> WorksheetData {
+    let totals: BTreeMap<&Period, f64> = periods
+        .iter()
+        .map(|period| {
+            let total = data
+                .values()
+                .filter_map(|by_cat| by_cat.get(period))
+                .filter(|v| **v > 0.0)
+                .sum::<f64>();
+            (period, total)
+        })
+        .collect();
+
+    data.iter()
+        .map(|(cat, by_cat)| {
+            let by_period = periods
+                .iter()
+                .filter_map(|period| by_cat.get(period).map(|v| (period, v)))
+                .map(|(period, v)| {
+                    let total = totals[period];
+                    let percent = if total == 0.0 { 0.0 } else { v / total * 100.0 };
+                    (period.clone(), percent)
+                })
+                .collect();
+            (cat.clone(), by_period)
+        })
+        .collect()
+}
+
+pub fn worksheet_data_to_periods(data: &Vec<WorksheetData>) -> Vec<Period> {
+    let mut periods: BTreeSet<Period> = BTreeSet::new();
+
+    for worksheet_data in data {
+        for (_cat, by_cat) in worksheet_data {
+            for (period, _by_period) in by_cat {
+                periods.insert(period.clone());
+            }
+        }
+    }
+
+    periods.into_iter().collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MissingPeriodStrategy {
+    Zero,
+    Interpolate,
+    Skip,
+}
+
+impl FromStr for MissingPeriodStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "zero" => Ok(MissingPeriodStrategy::Zero),
+            "interpolate" => Ok(MissingPeriodStrategy::Interpolate),
+            "skip" => Ok(MissingPeriodStrategy::Skip),
+            _ => Err(format!("Unknown missing period strategy '{}'", s)),
+        }
+    }
+}
+
+pub fn interpolate_gaps(values: &[f64]) -> Vec<f64> {
+    let known: Vec<usize> = (0..values.len()).filter(|i| values[*i] != 0.0).collect();
+
+    (0..values.len())
+        .map(|i| {
+            if values[i] != 0.0 {
+                return values[i];
+            }
+            let prev = known.iter().rev().find(|k| **k < i);
+            let next = known.iter().find(|k| **k > i);
+            match (prev, next) {
+                (Some(&a), Some(&b)) => {
+                    values[a] + (values[b] - values[a]) * (i - a) as f64 / (b - a) as f64
+                }
+                _ => 0.0,
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Smoothing {
+    None,
+    Mean,
+    Median,
+}
+
+impl FromStr for Smoothing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Smoothing::None),
+            "mean" => Ok(Smoothing::Mean),
+            "median" => Ok(Smoothing::Median),
+            _ => Err(format!("Unknown smoothing '{}'", s)),
+        }
+    }
+}
+
+fn smooth_with(values: &[f64], window: usize, f: fn(&[f64]) -> f64) -> Vec<f64> {
+    let window = window.max(1);
+    (0..values.len())
+        .map(|i| {
+            let known: Vec<f64> = values[(i + 1).saturating_sub(window)..=i]
+                .iter()
+                .cloned()
+                .filter(|v| !v.is_nan())
+                .collect();
+            if values[i].is_nan() || known.is_empty() {
+                f64::NAN
+            } else {
+                f(&known)
+            }
+        })
+        .collect()
+}
+
+pub fn moving_average(values: &[f64], window: usize) -> Vec<f64> {
+    smooth_with(values, window, mean)
+}
+
+pub fn moving_median(values: &[f64], window: usize) -> Vec<f64> {
+    smooth_with(values, window, median)
+}
+
+fn smooth(values: Vec<f64>, smoothing: Smoothing, window: usize) -> Vec<f64> {
+    match smoothing {
+        Smoothing::None => values,
+        Smoothing::Mean => moving_average(&values, window),
+        Smoothing::Median => moving_median(&values, window),
+    }
+}
+
+fn y_with_strategy(
+    by_cat: &BTreeMap<Period, f64>,
+    periods: &[Period],
+    strategy: MissingPeriodStrategy,
+) -> Vec<f64> {
+    match strategy {
+        MissingPeriodStrategy::Zero => y(by_cat, periods),
+        MissingPeriodStrategy::Interpolate => interpolate_gaps(&y(by_cat, periods)),
+        MissingPeriodStrategy::Skip => periods
+            .iter()
+            .map(|p| by_cat.get(p).cloned().unwrap_or(f64::NAN))
+            .collect(),
+    }
+}
+
+fn y(by_cat: &BTreeMap<Period, f64>, periods: &[Period]) -> Vec<f64> {
+    periods
+        .iter()
+        .map(|p| by_cat.get(p).unwrap_or(&0.0).clone())
+        .collect()
+}
+
+const HTML_TABLE_STYLE: &str = "<style>\
+table.money-manager { border-collapse: collapse; font-family: sans-serif; font-size: 14px; }\
+table.money-manager th, table.money-manager td { border: 1px solid #ccc; padding: 4px 8px; }\
+table.money-manager td.value { text-align: right; }\
+table.money-manager tr:hover td { filter: brightness(90%); }\
+</style>";
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn cell_color(value: f64, max: f64) -> String {
+    let ratio = if max > 0.0 { (value / max).max(0.0).min(1.0) } else { 0.0 };
+    let (low, high) = ([198.0, 239.0, 206.0], [255.0, 199.0, 206.0]);
+    let channel = |i: usize| (low[i] + (high[i] - low[i]) * ratio).round() as u8;
+    format!("#{:02x}{:02x}{:02x}", channel(0), channel(1), channel(2))
+}
+
+pub fn export_html_table(
+    data: &WorksheetData,
+    periods: &[Period],
+    writer: &mut impl Write,
+) -> Result<(), MyCustomError> {
+    let column_max: Vec<f64> = periods
+        .iter()
+        .map(|period| {
+            data.values()
+                .filter_map(|by_cat| by_cat.get(period))
+                .cloned()
+                .fold(0.0, f64::max)
+        })
+        .collect();
+
+    writeln!(writer, "{}", HTML_TABLE_STYLE)?;
+    writeln!(writer, "<table class=\"money-manager\">")?;
+    write!(writer, "<tr><th>Category</th>")?;
+    for period in periods {
+        write!(writer, "<th>{}</th>", html_escape(period))?;
+    }
+    writeln!(writer, "</tr>")?;
+
+    for (cat, by_cat) in data {
+        write!(writer, "<tr><td>{}</td>", html_escape(cat))?;
+        for (value, max) in y(by_cat, periods).iter().zip(column_max.iter()) {
+            if value.is_nan() {
+                write!(writer, "<td class=\"value\">—</td>")?;
+                continue;
+            }
+            write!(
+                writer,
+                "<td class=\"value\" style=\"background-color: {}\">{:.2}</td>",
+                cell_color(*value, *max),
+                value
+            )?;
+        }
+        writeln!(writer, "</tr>")?;
+    }
+
+    writeln!(writer, "</table>")?;
+    Ok(())
+}
+
+fn write_xlsx_table(
+    sheet: &mut Worksheet,
+    rows: &[(String, Vec<f64>)],
+    periods: &[Period],
+    formats: &[Format; 3],
+) -> Result<(), MyCustomError> {
+    let [header, number, striped] = formats;
+
+    sheet.write_string(0, 0, "Category", Some(header))?;
+    for (col, period) in periods.iter().enumerate() {
+        sheet.write_string(0, col as u16 + 1, period, Some(header))?;
+    }
+    for (row, (label, values)) in rows.iter().enumerate() {
+        let row = row as u32 + 1;
+        let format = if row % 2 == 0 { striped } else { number };
+        sheet.write_string(row, 0, label, Some(format))?;
+        for (col, value) in values.iter().enumerate() {
+            sheet.write_number(row, col as u16 + 1, *value, Some(format))?;
+        }
+    }
+    Ok(())
+}
+
+pub fn export_xlsx(
+    data: &[WorksheetData],
+    periods: &[Period],
+    path: &Path,
+) -> Result<(), MyCustomError> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| MyCustomError::InvalidConfig(format!("bad path {}", path.display())))?;
+    let workbook = Workbook::new(path);
+    let formats = [
+        workbook.add_format().set_bold(),
+        workbook.add_format().set_num_format("#,##0"),
+        workbook
+            .add_format()
+            .set_num_format("#,##0")
+            .set_bg_color(FormatColor::Custom(0xF2F2F2)),
+    ];
+
+    let mut totals = vec![0.0; periods.len()];
+    for (i, worksheet_data) in data.iter().enumerate() {
+        let rows: Vec<(String, Vec<f64>)> = worksheet_data
+            .iter()
+            .map(|(cat, by_cat)| (cat.to_string(), y(by_cat, periods)))
+            .collect();
+        for (_cat, values) in &rows {
+            for (total, value) in totals.iter_mut().zip(values) {
+                *total += value;
+            }
+        }
+        let mut sheet = workbook.add_worksheet(Some(&format!("Sheet {}", i + 1)))?;
+        write_xlsx_table(&mut sheet, &rows, periods, &formats)?;
+    }
+
+    {
+        let mut summary = workbook.add_worksheet(Some("Summary"))?;
+        write_xlsx_table(&mut summary, &[(String::from("Всего"), totals)], periods, &formats)?;
+    }
+
+    workbook.close()?;
+    Ok(())
+}
+
+pub fn generate_sample_xlsx(
+    path: &Path,
+    months: u32,
+    categories: &[&str],
+) -> Result<(), MyCustomError> {
+    let path = path
+        .to_str()
+        .ok_or_else(|| MyCustomError::InvalidConfig(format!("bad path {}", path.display())))?;
+    let headers = ColumnHeaders::default();
+    let labels = TxTypeLabels::default();
+    let mut rng = rand::thread_rng();
+    let today = chrono::Local::today().naive_local();
+
+    let workbook = Workbook::new(path);
+    {
+        let mut sheet = workbook.add_worksheet(None)?;
+        let header = workbook.add_format().set_bold();
+        let columns = [&headers.period, &headers.category, &headers.tx_type, &headers.value];
+        for (col, name) in columns.iter().enumerate() {
+            sheet.write_string(0, col as u16, name, Some(&header))?;
+        }
+
+        let base: Vec<f64> = categories.iter().map(|_| rng.gen_range(500.0..20000.0)).collect();
+        let mut row = 1;
+        for month in (0..months).rev() {
+            let start = months_back(today, month);
+            for (cat, base) in categories.iter().zip(&base) {
+                for _ in 0..rng.gen_range(1..=4) {
+                    let date = start.with_day(rng.gen_range(1..=28)).unwrap_or(start);
+                    let value = (base * rng.gen_range(0.5..1.5)).round();
+                    sheet.write_string(row, 0, &date.format("%d.%m.%Y").to_string(), None)?;
+                    sheet.write_string(row, 1, cat, None)?;
+                    sheet.write_string(row, 2, &labels.outcome, None)?;
+                    sheet.write_number(row, 3, value, None)?;
+                    row += 1;
+                }
+            }
+        }
+    }
+
+    workbook.close()?;
+    Ok(())
+}
+
+#[derive(Serialize, Deserialize)]
+struct NdjsonRecord {
+    sheet: usize,
+    category: String,
+    period: String,
+    value: f64,
+}
+
+pub fn export_ndjson(
+    data: &[WorksheetData],
+    periods: &[Period],
+    writer: &mut impl Write,
+) -> Result<(), MyCustomError> {
+    for (sheet, worksheet_data) in data.iter().enumerate() {
+        for (cat, by_cat) in worksheet_data {
+            for period in periods {
+                if let Some(value) = by_cat.get(period) {
+                    let record = NdjsonRecord {
+                        sheet,
+                        category: cat.to_string(),
+                        period: period.to_string(),
+                        value: *value,
+                    };
+                    serde_json::to_writer(&mut *writer, &record)?;
+                    writeln!(writer)?;
+                }
+            }
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn import_ndjson(reader: impl BufRead) -> Result<Vec<WorksheetData>, MyCustomError> {
+    let mut result: Vec<WorksheetData> = Vec::new();
+
+    for line in reader.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: NdjsonRecord = serde_json::from_str(&line)?;
+        if result.len() <= record.sheet {
+            result.resize(record.sheet + 1, BTreeMap::new());
+        }
+        *result[record.sheet]
+            .entry(Category::from(record.category))
+            .or_insert(BTreeMap::new())
+            .entry(Period::from(record.period))
+            .or_insert(0.0) += record.value;
+    }
+
+    Ok(result)
+}
+
+pub fn export_gnuplot_dat(
+    data: &WorksheetData,
+    periods: &[Period],
+    writer: &mut impl Write,
+) -> Result<(), MyCustomError> {
+    for (i, (cat, by_cat)) in data.iter().enumerate() {
+        if i > 0 {
+            writeln!(writer)?;
+            writeln!(writer)?;
+        }
+        writeln!(writer, "# {}", cat)?;
+        for (period, value) in periods.iter().zip(y(by_cat, periods)) {
+            writeln!(writer, "\"{}\" {:.2}", period, value)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+fn write_gnuplot_script(
+    data: &WorksheetData,
+    title: &str,
+    dat_file: &str,
+    chart_file: &str,
+    config: &DrawConfig,
+    writer: &mut impl Write,
+) -> Result<(), MyCustomError> {
+    writeln!(writer, "set terminal svg size {},{} dynamic", config.width, config.height)?;
+    writeln!(writer, "set output '{}'", chart_file)?;
+    writeln!(writer, "set title \"{}\"", title.replace('"', "'"))?;
+    writeln!(writer, "set grid")?;
+    writeln!(writer, "set key outside right top")?;
+    writeln!(writer, "set xtics rotate by -45")?;
+
+    let traces: Vec<String> = data
+        .keys()
+        .enumerate()
+        .map(|(i, cat)| {
+            format!(
+                "'{}' index {} using 0:2:xtic(1) with linespoints title \"{}\"",
+                dat_file,
+                i,
+                cat.replace('"', "'")
+            )
+        })
+        .collect();
+    writeln!(writer, "plot {}", traces.join(", \\\n     "))?;
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn export_plain_text_report(
+    data: &WorksheetData,
+    periods: &[Period],
+    writer: &mut impl Write,
+) -> Result<(), MyCustomError> {
+    let label = "Категория";
+    let average_label = "Среднее";
+    let width = data
+        .keys()
+        .map(|cat| cat.chars().count())
+        .chain(std::iter::once(label.chars().count()))
+        .max()
+        .unwrap_or_default();
+    let amount = |value: f64| format_currency(value, "", CurrencyPosition::Prefix);
+    let divider = "-".repeat(width + (periods.len() + 1) * 16);
+    let period_count = periods.len().max(1) as f64;
+    let with_average = |values: Vec<f64>| -> Vec<String> {
+        let average = values.iter().sum::<f64>() / period_count;
+        values.into_iter().chain(std::iter::once(average)).map(amount).collect()
+    };
+
+    let mut write_row = |name: &str, values: &[String]| -> io::Result<()> {
+        write!(writer, "{:<width$}", name, width = width)?;
+        for value in values {
+            write!(writer, " {:>15}", value)?;
+        }
+        writeln!(writer)
+    };
+
+    let header: Vec<String> = periods
+        .iter()
+        .map(|period| period.to_string())
+        .chain(std::iter::once(average_label.to_string()))
+        .collect();
+    write_row(label, &header)?;
+    write_row(&divider, &[])?;
+
+    let mut totals = vec![0.0; periods.len()];
+    for (cat, by_cat) in data {
+        let values = y(by_cat, periods);
+        for (total, value) in totals.iter_mut().zip(&values) {
+            *total += value;
+        }
+        write_row(cat, &with_average(values))?;
+    }
+
+    write_row(&divider, &[])?;
+    let category_count = data.len().max(1) as f64;
+    let averages: Vec<String> = totals.iter().map(|total| amount(total / category_count)).collect();
+    write_row("Всего", &with_average(totals))?;
+    write_row(average_label, &averages)?;
+    writer.flush()?;
+    Ok(())
+}
+
+fn prometheus_label(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+pub fn export_prometheus_metrics(
+    data: &WorksheetData,
+    periods: &[Period],
+    writer: &mut impl Write,
+) -> Result<(), MyCustomError> {
+    export_prometheus_metrics_with_name(data, periods, DEFAULT_METRIC_NAME, writer)
+}
+
+pub fn export_prometheus_metrics_with_name(
+    data: &WorksheetData,
+    periods: &[Period],
+    metric_name: &str,
+    writer: &mut impl Write,
+) -> Result<(), MyCustomError> {
+    let metrics_error = |e: io::Error| MyCustomError::MetricsExportError(e.to_string());
+
+    writeln!(writer, "# HELP {} Amount spent per category and period.", metric_name)
+        .map_err(metrics_error)?;
+    writeln!(writer, "# TYPE {} gauge", metric_name).map_err(metrics_error)?;
+    for (cat, by_cat) in data {
+        for (period, value) in periods.iter().zip(y(by_cat, periods)) {
+            writeln!(
+                writer,
+                "{}{{category=\"{}\",period=\"{}\"}} {}",
+                metric_name,
+                prometheus_label(cat),
+                prometheus_label(period),
+                value
+            )
+            .map_err(metrics_error)?;
+        }
+    }
+    writer.flush().map_err(metrics_error)
+}
+
+pub fn export_csv(
+    data: &WorksheetData,
+    periods: &[Period],
+    writer: &mut impl Write,
+) -> Result<(), MyCustomError> {
+    let mut csv_writer = WriterBuilder::new().from_writer(writer);
+
+    let mut header = vec![String::from("Category")];
+    header.extend(periods.iter().map(|p| p.to_string()));
+    csv_writer.write_record(&header)?;
+
+    write_csv_rows(&mut csv_writer, None, data, periods)?;
+
+    csv_writer.flush()?;
+    Ok(())
+}
+
+pub fn export_csv_multi(
+    data: &[WorksheetData],
+    periods: &[Period],
+    writer: &mut impl Write,
+) -> Result<(), MyCustomError> {
+    let mut csv_writer = WriterBuilder::new().from_writer(writer);
+
+    let mut header = vec![String::from("Sheet"), String::from("Category")];
+    header.extend(periods.iter().map(|p| p.to_string()));
+    csv_writer.write_record(&header)?;
+
+    for (sheet, worksheet_data) in data.iter().enumerate() {
+        write_csv_rows(&mut csv_writer, Some(sheet), worksheet_data, periods)?;
+    }
+
+    csv_writer.flush()?;
+    Ok(())
+}
+
+fn write_csv_rows<W: Write>(
+    csv_writer: &mut csv::Writer<W>,
+    sheet: Option<usize>,
+    data: &WorksheetData,
+    periods: &[Period],
+) -> Result<(), MyCustomError> {
+    for (cat, by_cat) in data {
+        let mut record: Vec<String> = sheet.iter().map(|i| i.to_string()).collect();
+        record.push(cat.to_string());
+        record.extend(y(by_cat, periods).iter().map(|v| v.to_string()));
+        csv_writer.write_record(&record)?;
+    }
+    Ok(())
+}
+
+pub fn export_sqlite(
+    data: &[WorksheetData],
+    periods: &[Period],
+    db_path: &Path,
+) -> Result<(), MyCustomError> {
+    export_sqlite_with_mode(data, periods, db_path, false)
+}
+
+pub fn export_sqlite_with_mode(
+    data: &[WorksheetData],
+    periods: &[Period],
+    db_path: &Path,
+    overwrite: bool,
+) -> Result<(), MyCustomError> {
+    let mut conn = Connection::open(db_path)?;
+    let tx = conn.transaction()?;
+
+    if overwrite {
+        tx.execute_batch(
+            "DROP TABLE IF EXISTS transactions;
+             DROP TABLE IF EXISTS periods;",
+        )?;
+    }
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS transactions (
+             sheet_id INTEGER,
+             category TEXT,
+             period TEXT,
+             value REAL
+         );
+         CREATE TABLE IF NOT EXISTS periods (
+             period TEXT PRIMARY KEY,
+             sort_key INTEGER
+         );",
+    )?;
+
+    {
+        let mut insert_period =
+            tx.prepare("INSERT OR REPLACE INTO periods (period, sort_key) VALUES (?1, ?2)")?;
+        for (sort_key, period) in periods.iter().enumerate() {
+            insert_period.execute(params![period.as_str(), sort_key as i64])?;
+        }
+
+        let mut insert_transaction = tx.prepare(
+            "INSERT INTO transactions (sheet_id, category, period, value) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (sheet_id, worksheet_data) in data.iter().enumerate() {
+            for (cat, by_cat) in worksheet_data {
+                for period in periods {
+                    if let Some(value) = by_cat.get(period) {
+                        insert_transaction.execute(params![
+                            sheet_id as i64,
+                            cat.as_str(),
+                            period.as_str(),
+                            value
+                        ])?;
+                    }
+                }
+            }
+        }
+    }
+
+    tx.commit()?;
+    Ok(())
+}
+
+pub fn detect_recurring_categories(
+    data: &WorksheetData,
+    periods: &[Period],
+    threshold: f64,
+) -> Vec<Category> {
+    data.iter()
+        .filter(|(_cat, by_cat)| is_recurring(by_cat, periods, threshold))
+        .map(|(cat, _by_cat)| cat.clone())
+        .collect()
+}
+
+pub fn detect_irregular_categories(
+    data: &WorksheetData,
+    periods: &[Period],
+    threshold: f64,
+) -> Vec<Category> {
+    data.iter()
+        .filter(|(_cat, by_cat)| !is_recurring(by_cat, periods, threshold))
+        .map(|(cat, _by_cat)| cat.clone())
+        .collect()
+}
+
+fn is_recurring(by_cat: &BTreeMap<Period, f64>, periods: &[Period], threshold: f64) -> bool {
+    if periods.is_empty() {
+        return false;
+    }
+    let present = y(by_cat, periods).iter().filter(|v| **v != 0.0).count();
+    present as f64 / periods.len() as f64 >= threshold
+}
+
+#[derive(Debug, Clone)]
+pub struct PeriodComparison {
+    pub category: Category,
+    pub v1: f64,
+    pub v2: f64,
+    pub delta: f64,
+    pub delta_pct: f64,
+}
+
+pub fn compare_periods(data: &WorksheetData, p1: &Period, p2: &Period) -> Vec<PeriodComparison> {
+    let mut result: Vec<PeriodComparison> = data
+        .iter()
+        .map(|(cat, by_cat)| {
+            let v1 = by_cat.get(p1).cloned().unwrap_or(0.0);
+            let v2 = by_cat.get(p2).cloned().unwrap_or(0.0);
+            let delta = v2 - v1;
+            PeriodComparison {
+                category: cat.clone(),
+                v1,
+                v2,
+                delta,
+                delta_pct: if v1 == 0.0 { f64::NAN } else { delta / v1 * 100.0 },
+            }
+        })
+        .collect();
+
+    result.sort_by(|a, b| {
+        b.delta
+            .abs()
+            .partial_cmp(&a.delta.abs())
+            .unwrap_or(Ordering::Equal)
+    });
+    result
+}
+
+pub fn period_summary(data: &WorksheetData, periods: &[Period]) -> BTreeMap<Period, f64> {
+    let mut result: BTreeMap<Period, f64> = periods.iter().map(|p| (p.clone(), 0.0)).collect();
+    if periods.is_empty() {
+        return result;
+    }
+
+    for by_cat in data.values() {
+        let y_values = y(by_cat, periods);
+        if !is_spending_category(&y_values) {
+            continue;
+        }
+        for (period, value) in periods.iter().zip(y_values) {
+            *result.get_mut(period).unwrap() += value;
+        }
+    }
+
+    result
+}
+
+fn sorted_period_totals(data: &WorksheetData, periods: &[Period]) -> Vec<(Period, f64)> {
+    let mut totals: Vec<_> = period_summary(data, periods).into_iter().collect();
+    totals.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+    totals
+}
+
+pub fn top_spending_months(data: &WorksheetData, periods: &[Period], n: usize) -> Vec<(Period, f64)> {
+    sorted_period_totals(data, periods).into_iter().take(n).collect()
+}
+
+pub fn bottom_spending_months(
+    data: &WorksheetData,
+    periods: &[Period],
+    n: usize,
+) -> Vec<(Period, f64)> {
+    sorted_period_totals(data, periods)
+        .into_iter()
+        .rev()
+        .take(n)
+        .collect()
+}
+
+pub fn pearson_correlation(a: &[f64], b: &[f64]) -> f64 {
+    let n = a.len().min(b.len());
+    if n < 2 {
+        return f64::NAN;
+    }
+    let a_mean = a[..n].iter().sum::<f64>() / n as f64;
+    let b_mean = b[..n].iter().sum::<f64>() / n as f64;
+
+    let mut covariance = 0.0;
+    let mut a_variance = 0.0;
+    let mut b_variance = 0.0;
+    for i in 0..n {
+        let da = a[i] - a_mean;
+        let db = b[i] - b_mean;
+        covariance += da * db;
+        a_variance += da * da;
+        b_variance += db * db;
+    }
+
+    if a_variance == 0.0 || b_variance == 0.0 {
+        return f64::NAN;
+    }
+    covariance / (a_variance * b_variance).sqrt()
+}
+
+pub fn category_correlation(
+    data: &WorksheetData,
+    periods: &[Period],
+) -> BTreeMap<(Category, Category), f64> {
+    let values: Vec<(&Category, Vec<f64>)> = data
+        .iter()
+        .map(|(cat, by_cat)| (cat, y(by_cat, periods)))
+        .collect();
+
+    let mut result = BTreeMap::new();
+    for (i, (a, a_values)) in values.iter().enumerate() {
+        for (b, b_values) in values.iter().skip(i) {
+            let r = pearson_correlation(a_values, b_values);
+            result.insert(((*a).clone(), (*b).clone()), r);
+            result.insert(((*b).clone(), (*a).clone()), r);
+        }
+    }
+    result
+}
+
+fn seasonal_position(period: &Period, group_by: &GroupBy) -> Option<(usize, usize)> {
+    let date = parse_period_string(period, group_by.clone())?;
+    Some(match group_by {
+        GroupBy::Month => (date.month0() as usize, 12),
+        // Overlapping windows are keyed by the month they end in.
+        GroupBy::RollingQuarter => (date.month0() as usize, 12),
+        GroupBy::Quarter => (date.month0() as usize / 3, 4),
+        GroupBy::Year => (0, 1),
+        GroupBy::WeekOfMonth => ((date.day0() / 7) as usize, 5),
+        GroupBy::Custom(_) => return None,
+    })
+}
+
+pub fn seasonality_index(
+    data: &WorksheetData,
+    periods: &[Period],
+    group_by: GroupBy,
+) -> BTreeMap<Category, Vec<f64>> {
+    let positions: Vec<Option<(usize, usize)>> = periods
+        .iter()
+        .map(|period| seasonal_position(period, &group_by))
+        .collect();
+    let len = positions.iter().flatten().map(|(_pos, len)| *len).max().unwrap_or(0);
+
+    data.iter()
+        .map(|(cat, by_cat)| {
+            let y_values = y(by_cat, periods);
+            let avg = if y_values.is_empty() { 0.0 } else { mean(&y_values) };
+            let mut sums = vec![0.0; len];
+            let mut counts = vec![0; len];
+            for (value, position) in y_values.iter().zip(positions.iter()) {
+                if let Some((pos, _len)) = position {
+                    sums[*pos] += value;
+                    counts[*pos] += 1;
+                }
+            }
+            let index = sums
+                .iter()
+                .zip(counts.iter())
+                .map(|(sum, count)| {
+                    if *count == 0 || avg == 0.0 {
+                        f64::NAN
+                    } else {
+                        sum / *count as f64 / avg
+                    }
+                })
+                .collect();
+            (cat.clone(), index)
+        })
+        .collect()
+}
+
+pub fn compute_spending_velocity(
+    data: &WorksheetData,
+    periods: &[Period],
+    group_by: GroupBy,
+) -> BTreeMap<Category, f64> {
+    let days: Vec<Option<i64>> = periods
+        .iter()
+        .map(|period| days_in_period(period, &group_by))
+        .collect();
+
+    data.iter()
+        .map(|(cat, by_cat)| {
+            let rates: Vec<f64> = y(by_cat, periods)
+                .into_iter()
+                .zip(days.iter())
+                .filter_map(|(value, days)| days.map(|days| value / days as f64))
+                .collect();
+            let rate = if rates.is_empty() {
+                0.0
+            } else {
+                rates.iter().sum::<f64>() / rates.len() as f64
+            };
+            (cat.clone(), rate)
+        })
+        .collect()
+}
+
+pub fn linear_regression(x: &[f64], y: &[f64]) -> (f64, f64) {
+    let n = x.len().min(y.len());
+    if n == 0 {
+        return (0.0, 0.0);
+    }
+    let x_mean = x[..n].iter().sum::<f64>() / n as f64;
+    let y_mean = y[..n].iter().sum::<f64>() / n as f64;
+
+    let mut covariance = 0.0;
+    let mut variance = 0.0;
+    for i in 0..n {
+        covariance += (x[i] - x_mean) * (y[i] - y_mean);
+        variance += (x[i] - x_mean) * (x[i] - x_mean);
+    }
+    if variance == 0.0 {
+        return (0.0, y_mean);
+    }
+
+    let slope = covariance / variance;
+    (slope, y_mean - slope * x_mean)
+}
+
+pub fn forecast_next_period(data: &WorksheetData, periods: &[Period]) -> BTreeMap<Category, f64> {
+    let x: Vec<f64> = (0..periods.len()).map(|i| i as f64).collect();
+
+    data.iter()
+        .map(|(cat, by_cat)| {
+            let y_values = y(by_cat, periods);
+            let forecast = if y_values.len() < 2 || y_values.iter().all(|v| *v == 0.0) {
+                y_values.last().cloned().unwrap_or(0.0)
+            } else {
+                let (slope, intercept) = linear_regression(&x, &y_values);
+                slope * periods.len() as f64 + intercept
+            };
+            (cat.clone(), forecast)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct AnomalyRecord {
+    pub category: Category,
+    pub period: Period,
+    pub value: f64,
+    pub z_score: f64,
+}
+
+pub fn detect_anomalies(data: &WorksheetData, periods: &[Period], sigma: f64) -> Vec<AnomalyRecord> {
+    if periods.len() < 2 {
+        return Vec::new();
+    }
+
+    let mut result = Vec::new();
+    for (cat, by_cat) in data {
+        let y_values = y(by_cat, periods);
+        let avg = mean(&y_values);
+        let stddev = population_standard_deviation(&y_values, Some(avg));
+        if stddev == 0.0 {
+            continue;
+        }
+        for (period, value) in periods.iter().zip(y_values.iter()) {
+            let z_score = (value - avg) / stddev;
+            if z_score.abs() > sigma {
+                result.push(AnomalyRecord {
+                    category: cat.clone(),
+                    period: period.clone(),
+                    value: *value,
+                    z_score,
+                });
+            }
+        }
+    }
+    result
+}
+
+#[derive(Debug, Clone)]
+pub struct CategoryStats {
+    pub category: Category,
+    pub mean: f64,
+    pub median: f64,
+    pub min: f64,
+    pub max: f64,
+    pub growth_rate: f64,
+    pub p5: f64,
+    pub p25: f64,
+    pub p75: f64,
+    pub p95: f64,
+    pub iqr: f64,
+}
+
+pub fn compute_percentile(values: &mut Vec<f64>, p: f64) -> f64 {
+    if values.is_empty() {
+        return f64::NAN;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+
+    let rank = p.max(0.0).min(100.0) / 100.0 * (values.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    values[lower] + (values[upper] - values[lower]) * (rank - lower as f64)
+}
+
+pub fn compute_growth_rate(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let non_zero = |v: f64| if v == 0.0 { f64::EPSILON } else { v };
+    let start = non_zero(values[0]);
+    let end = non_zero(values[values.len() - 1]);
+    (end / start).powf(1.0 / (values.len() - 1) as f64) - 1.0
+}
+
+pub fn category_stats(data: &WorksheetData, periods: &[Period]) -> Vec<CategoryStats> {
+    if periods.is_empty() {
+        return Vec::new();
+    }
+
+    data.iter()
+        .map(|(cat, by_cat)| {
+            let y_values = y(by_cat, periods);
+            let mut sorted = y_values.clone();
+            let p25 = compute_percentile(&mut sorted, 25.0);
+            let p75 = compute_percentile(&mut sorted, 75.0);
+            CategoryStats {
+                category: cat.clone(),
+                mean: mean(&y_values),
+                median: median(&y_values),
+                min: y_values.iter().cloned().fold(f64::INFINITY, f64::min),
+                max: y_values.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                growth_rate: compute_growth_rate(&y_values),
+                p5: compute_percentile(&mut sorted, 5.0),
+                p25,
+                p75,
+                p95: compute_percentile(&mut sorted, 95.0),
+                iqr: p75 - p25,
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone)]
+pub struct WorksheetSummary {
+    pub total_periods: usize,
+    pub total_categories: usize,
+    pub total_income: f64,
+    pub total_expense: f64,
+    pub net: f64,
+    pub top_category: Category,
+    pub most_volatile_category: Category,
+    pub gini: f64,
+}
+
+pub fn lorenz_curve(values: &[f64]) -> Vec<(f64, f64)> {
+    let mut sorted: Vec<f64> = values.iter().map(|v| v.max(0.0)).collect();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+    let total: f64 = sorted.iter().sum();
+    if sorted.is_empty() || total == 0.0 {
+        return Vec::new();
+    }
+
+    let n = sorted.len() as f64;
+    let mut cumulative = 0.0;
+    let mut result = vec![(0.0, 0.0)];
+    for (i, v) in sorted.iter().enumerate() {
+        cumulative += v;
+        result.push(((i + 1) as f64 / n, cumulative / total));
+    }
+    result
+}
+
+pub fn compute_gini_coefficient(values: &[f64]) -> f64 {
+    let curve = lorenz_curve(values);
+    if curve.is_empty() {
+        return 0.0;
+    }
+    let area: f64 = curve
+        .windows(2)
+        .map(|pair| (pair[1].0 - pair[0].0) * (pair[1].1 + pair[0].1) / 2.0)
+        .sum();
+    1.0 - 2.0 * area
+}
+
+pub fn worksheet_data_summary(data: &WorksheetData, periods: &[Period]) -> WorksheetSummary {
+    let mut total_income = 0.0;
+    let mut total_expense = 0.0;
+    let mut top_category = (Category::default(), f64::MIN);
+    let mut most_volatile_category = (Category::default(), f64::MIN);
+    let mut expense_totals = Vec::new();
+
+    for (cat, by_cat) in data {
+        let y_values = y(by_cat, periods);
+        let total = y_values.iter().sum::<f64>();
+        if total < 0.0 {
+            total_income -= total;
+            continue;
+        }
+        total_expense += total;
+        expense_totals.push(total);
+        if total > top_category.1 {
+            top_category = (cat.clone(), total);
+        }
+        if !y_values.is_empty() {
+            let stddev = population_standard_deviation(&y_values, None);
+            if stddev > most_volatile_category.1 {
+                most_volatile_category = (cat.clone(), stddev);
+            }
+        }
+    }
+
+    WorksheetSummary {
+        total_periods: periods.len(),
+        total_categories: data.len(),
+        total_income,
+        total_expense,
+        net: total_income - total_expense,
+        top_category: top_category.0,
+        most_volatile_category: most_volatile_category.0,
+        gini: compute_gini_coefficient(&expense_totals),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BudgetVarianceRecord {
+    pub category: Category,
+    pub period: Period,
+    pub actual: f64,
+    pub budget: f64,
+    pub variance: f64,
+    pub pct_over_budget: f64,
+}
+
+pub fn read_budget_file(file: &str) -> Result<BTreeMap<Category, f64>, MyCustomError> {
+    let mut reader = ReaderBuilder::new().trim(csv::Trim::All).from_path(file)?;
+    let mut budgets = BTreeMap::new();
+
+    for record in reader.deserialize() {
+        let (category, amount): (String, f64) =
+            record.map_err(|e| MyCustomError::InvalidConfig(e.to_string()))?;
+        budgets.insert(Category::from(category), amount);
+    }
+
+    Ok(budgets)
+}
+
+pub fn budgets_per_period(
+    budgets: BTreeMap<Category, f64>,
+    group_by: &GroupBy,
+) -> Result<BTreeMap<Category, f64>, MyCustomError> {
+    let months = months_in_period(group_by);
+    if months == 0 {
+        return Err(MyCustomError::InvalidConfig(format!(
+            "monthly budgets can't be applied to '{}' periods",
+            group_by
+        )));
+    }
+    Ok(budgets
+        .into_iter()
+        .map(|(cat, budget)| (cat, budget * months as f64))
+        .collect())
+}
+
+pub fn compute_budget_adherence_score(variance_records: &[BudgetVarianceRecord]) -> f64 {
+    let mut by_category: BTreeMap<&Category, (f64, f64)> = BTreeMap::new();
+    for record in variance_records {
+        let (budget, actual) = by_category.entry(&record.category).or_insert((0.0, 0.0));
+        *budget += record.budget;
+        *actual += record.actual;
+    }
+    if by_category.is_empty() {
+        return 100.0;
+    }
+
+    let total: f64 = by_category
+        .values()
+        .map(|(budget, actual)| {
+            if *actual <= 0.0 {
+                1.0
+            } else {
+                (budget.max(0.0) / actual).min(1.0)
+            }
+        })
+        .sum();
+    total / by_category.len() as f64 * 100.0
+}
+
+pub fn budget_grade(score: f64) -> &'static str {
+    match score {
+        s if s >= 97.0 => "A+",
+        s if s >= 90.0 => "A",
+        s if s >= 80.0 => "B",
+        s if s >= 70.0 => "C",
+        s if s >= 60.0 => "D",
+        _ => "F",
+    }
+}
+
+pub fn read_cpi_file(file: &str) -> Result<BTreeMap<Period, f64>, MyCustomError> {
+    let mut reader = ReaderBuilder::new().trim(csv::Trim::All).from_path(file)?;
+    let mut cpi_rates = BTreeMap::new();
+
+    for record in reader.deserialize() {
+        let (period, index): (String, f64) =
+            record.map_err(|e| MyCustomError::InvalidConfig(e.to_string()))?;
+        cpi_rates.insert(Period::from(period), index);
+    }
+
+    Ok(cpi_rates)
+}
+
+pub fn apply_inflation_adjustment(
+    data: WorksheetData,
+    periods: &[Period],
+    cpi_rates: &BTreeMap<Period, f64>,
+    base_period: &Period,
+) -> Result<WorksheetData, MyCustomError> {
+    let cpi = |period: &Period| {
+        cpi_rates
+            .get(period)
+            .cloned()
+            .ok_or_else(|| MyCustomError::MissingCpiData { period: period.clone() })
+    };
+    let base = cpi(base_period)?;
+    let factors: BTreeMap<&Period, f64> = periods
+        .iter()
+        .map(|period| Ok((period, base / cpi(period)?)))
+        .collect::<Result<_, MyCustomError>>()?;
+
+    Ok(data
+        .into_iter()
+        .map(|(cat, by_cat)| {
+            let adjusted = by_cat
+                .into_iter()
+                .map(|(period, value)| {
+                    let factor = factors.get(&period).cloned().unwrap_or(1.0);
+                    (period, value * factor)
+                })
+                .collect();
+            (cat, adjusted)
+        })
+        .collect())
+}
+
+pub fn compute_monthly_budget_variance(
+    data: &WorksheetData,
+    budgets: &BTreeMap<Category, f64>,
+    periods: &[Period],
+) -> Vec<BudgetVarianceRecord> {
+    let mut result = Vec::new();
+    let empty = BTreeMap::new();
+
+    for (cat, budget) in budgets {
+        let by_cat = data.get(cat).unwrap_or(&empty);
+        for (period, actual) in periods.iter().zip(y(by_cat, periods)) {
+            let variance = actual - budget;
+            let pct_over_budget = if *budget != 0.0 {
+                variance / budget * 100.0
+            } else {
+                0.0
+            };
+            result.push(BudgetVarianceRecord {
+                category: cat.clone(),
+                period: period.clone(),
+                actual,
+                budget: *budget,
+                variance,
+                pct_over_budget,
+            });
+        }
+    }
+    result
+}
+
+fn default_min() -> f64 {
+    f64::NEG_INFINITY
+}
+
+fn default_max() -> f64 {
+    f64::INFINITY
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CategoryRule {
+    #[serde(default = "default_min")]
+    pub min: f64,
+    #[serde(default = "default_max")]
+    pub max: f64,
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Schema {
+    pub categories: BTreeMap<String, CategoryRule>,
+}
+
+pub fn read_schema(file: &str) -> Result<Schema, MyCustomError> {
+    let content = fs::read_to_string(file)
+        .map_err(|e| MyCustomError::ConfigError(e.to_string()))?;
+    serde_json::from_str(&content).map_err(|e| MyCustomError::ConfigError(e.to_string()))
+}
+
+#[derive(Debug, Clone)]
+pub enum ValidationViolation {
+    UnknownCategory(Category),
+    MissingCategory(Category),
+    OutOfRange {
+        category: Category,
+        period: Period,
+        value: f64,
+        min: f64,
+        max: f64,
+    },
+}
+
+impl fmt::Display for ValidationViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ValidationViolation::UnknownCategory(cat) => write!(f, "Unknown category '{}'", cat),
+            ValidationViolation::MissingCategory(cat) => {
+                write!(f, "Required category '{}' is missing", cat)
+            }
+            ValidationViolation::OutOfRange {
+                category,
+                period,
+                value,
+                min,
+                max,
+            } => write!(
+                f,
+                "Category '{}' in period '{}': {} is outside [{}, {}]",
+                category, period, value, min, max
+            ),
+        }
+    }
+}
+
+pub fn validate_against_schema(
+    data: &WorksheetData,
+    periods: &[Period],
+    schema: &Schema,
+) -> Vec<ValidationViolation> {
+    let mut result = Vec::new();
+
+    for (cat, by_cat) in data {
+        let rule = match schema.categories.get(cat.as_str()) {
+            Some(rule) => rule,
+            None => {
+                result.push(ValidationViolation::UnknownCategory(cat.clone()));
+                continue;
+            }
+        };
+        for period in periods {
+            if let Some(value) = by_cat.get(period) {
+                if *value < rule.min || *value > rule.max {
+                    result.push(ValidationViolation::OutOfRange {
+                        category: cat.clone(),
+                        period: period.clone(),
+                        value: *value,
+                        min: rule.min,
+                        max: rule.max,
+                    });
+                }
+            }
+        }
+    }
+
+    for (cat, rule) in &schema.categories {
+        if rule.required && !data.contains_key(cat.as_str()) {
+            result.push(ValidationViolation::MissingCategory(Category::from(cat.as_str())));
+        }
+    }
+
+    result
+}
+
+pub fn compute_savings_rate(data: &WorksheetData, periods: &[Period]) -> Vec<f64> {
+    let mut income = vec![0.0; periods.len()];
+    let mut expenses = vec![0.0; periods.len()];
+
+    for by_cat in data.values() {
+        let y_values = y(by_cat, periods);
+        let is_income = y_values.iter().sum::<f64>() < 0.0;
+        for (i, v) in y_values.iter().enumerate() {
+            if is_income {
+                income[i] -= v;
+            } else {
+                expenses[i] += v;
+            }
+        }
+    }
+
+    income
+        .iter()
+        .zip(expenses.iter())
+        .map(|(i, e)| if *i == 0.0 { f64::NAN } else { (i - e) / i * 100.0 })
+        .collect()
+}
+
+pub fn compute_expense_ratio(
+    data: &WorksheetData,
+    income_category: &str,
+    expense_categories: &[&str],
+    periods: &[Period],
+) -> Vec<f64> {
+    let values = |cat: &str| match data.get(cat) {
+        Some(by_cat) => y(by_cat, periods),
+        None => vec![0.0; periods.len()],
+    };
+    let income = values(income_category);
+    let mut expenses = vec![0.0; periods.len()];
+    for cat in expense_categories {
+        for (e, v) in expenses.iter_mut().zip(values(cat)) {
+            *e += v;
+        }
+    }
+
+    income
+        .iter()
+        .zip(expenses.iter())
+        .map(|(i, e)| if *i == 0.0 { f64::NAN } else { e / i.abs() })
+        .collect()
+}
+
+fn split_income_expense(data: &WorksheetData, periods: &[Period]) -> (WorksheetData, WorksheetData) {
+    let mut income = BTreeMap::new();
+    let mut expenses = BTreeMap::new();
+
+    for (cat, by_cat) in data {
+        if y(by_cat, periods).iter().sum::<f64>() < 0.0 {
+            let negated = by_cat.iter().map(|(period, v)| (period.clone(), -v)).collect();
+            income.insert(cat.clone(), negated);
+        } else {
+            expenses.insert(cat.clone(), by_cat.clone());
+        }
+    }
+
+    (income, expenses)
+}
+
+pub fn compute_cumulative_net_worth(
+    income_data: &WorksheetData,
+    expense_data: &WorksheetData,
+    periods: &[Period],
+    initial_balance: f64,
+) -> Vec<f64> {
+    let totals = |data: &WorksheetData| {
+        let mut result = vec![0.0; periods.len()];
+        for by_cat in data.values() {
+            for (t, v) in result.iter_mut().zip(y(by_cat, periods)) {
+                *t += v;
+            }
+        }
+        result
+    };
+    let income = totals(income_data);
+    let expenses = totals(expense_data);
+
+    let mut balance = initial_balance;
+    income
+        .iter()
+        .zip(expenses.iter())
+        .map(|(i, e)| {
+            balance += i - e;
+            balance
+        })
+        .collect()
+}
+
+pub fn pivot_to_category_share(data: &WorksheetData, periods: &[Period]) -> WorksheetData {
+    normalize_to_percent(data, periods)
+        .into_iter()
+        .map(|(cat, by_cat)| {
+            let shares = by_cat
+                .into_iter()
+                .map(|(period, percent)| (period, percent / 100.0))
+                .collect();
+            (cat, shares)
+        })
+        .collect()
+}
+
+pub fn compute_category_rank(
+    data: &WorksheetData,
+    periods: &[Period],
+) -> BTreeMap<Category, BTreeMap<Period, usize>> {
+    let mut result: BTreeMap<Category, BTreeMap<Period, usize>> = BTreeMap::new();
+
+    for period in periods {
+        let mut values: Vec<(&Category, f64)> = data
+            .iter()
+            .filter_map(|(cat, by_cat)| by_cat.get(period).map(|v| (cat, *v)))
+            .collect();
+        values.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+        for (rank, (cat, _value)) in values.into_iter().enumerate() {
+            result
+                .entry(cat.clone())
+                .or_insert(BTreeMap::new())
+                .insert(period.clone(), rank + 1);
+        }
+    }
+
+    result
+}
+
+fn period_over_period(
+    data: &WorksheetData,
+    periods: &[Period],
+    delta: impl Fn(f64, f64) -> f64,
+) -> WorksheetData {
+    data.iter()
+        .map(|(cat, by_cat)| {
+            let y_values = y(by_cat, periods);
+            let by_period = periods
+                .iter()
+                .enumerate()
+                .skip(1)
+                .map(|(i, period)| (period.clone(), delta(y_values[i - 1], y_values[i])))
+                .collect();
+            (cat.clone(), by_period)
+        })
+        .collect()
+}
+
+pub fn compute_period_over_period_delta(data: &WorksheetData, periods: &[Period]) -> WorksheetData {
+    period_over_period(data, periods, |prev, cur| {
+        if prev == 0.0 {
+            f64::NAN
+        } else {
+            (cur - prev) / prev * 100.0
+        }
+    })
+}
+
+pub fn compute_period_over_period_abs_delta(
+    data: &WorksheetData,
+    periods: &[Period],
+) -> WorksheetData {
+    period_over_period(data, periods, |prev, cur| cur - prev)
+}
+
+pub fn compute_zscore_table(data: &WorksheetData, periods: &[Period]) -> WorksheetData {
+    data.iter()
+        .map(|(cat, by_cat)| {
+            let y_values = y(by_cat, periods);
+            let (avg, stddev) = if y_values.is_empty() {
+                (0.0, 0.0)
+            } else {
+                (mean(&y_values), population_standard_deviation(&y_values, None))
+            };
+            let zscores = periods
+                .iter()
+                .zip(y_values)
+                .map(|(period, value)| {
+                    let z = if stddev == 0.0 { 0.0 } else { (value - avg) / stddev };
+                    (period.clone(), z)
+                })
+                .collect();
+            (cat.clone(), zscores)
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ViewMode {
+    Absolute,
+    Share,
+    DeltaPct,
+    DeltaAbs,
+    Hierarchical,
+    ZScore,
+    ByTag,
+}
+
+impl FromStr for ViewMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "absolute" => Ok(ViewMode::Absolute),
+            "share" => Ok(ViewMode::Share),
+            "delta-pct" => Ok(ViewMode::DeltaPct),
+            "delta-abs" => Ok(ViewMode::DeltaAbs),
+            "hierarchical" => Ok(ViewMode::Hierarchical),
+            "zscore" => Ok(ViewMode::ZScore),
+            "by-tag" => Ok(ViewMode::ByTag),
+            _ => Err(format!("Unknown view mode '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum SortOrder {
+    MeanDesc,
+    MeanAsc,
+    Alpha,
+    TotalDesc,
+}
+
+impl FromStr for SortOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mean-desc" => Ok(SortOrder::MeanDesc),
+            "mean-asc" => Ok(SortOrder::MeanAsc),
+            "alpha" => Ok(SortOrder::Alpha),
+            "total-desc" => Ok(SortOrder::TotalDesc),
+            _ => Err(format!("Unknown sort order '{}'", s)),
+        }
+    }
+}
+
+pub fn sort_categories<'a>(
+    data: &'a WorksheetData,
+    periods: &[Period],
+    order: SortOrder,
+) -> Vec<(&'a Category, &'a BTreeMap<Period, f64>)> {
+    let mut keyed: Vec<(f64, (&Category, &BTreeMap<Period, f64>))> = data
+        .iter()
+        .map(|(cat, by_cat)| {
+            let total: f64 = y(by_cat, periods).iter().sum();
+            let key = match order {
+                SortOrder::MeanDesc | SortOrder::MeanAsc => total / periods.len().max(1) as f64,
+                SortOrder::Alpha | SortOrder::TotalDesc => total,
+            };
+            (key, (cat, by_cat))
+        })
+        .collect();
+
+    match order {
+        SortOrder::Alpha => {}
+        SortOrder::MeanAsc => {
+            keyed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal))
+        }
+        SortOrder::MeanDesc | SortOrder::TotalDesc => {
+            keyed.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(Ordering::Equal))
+        }
+    }
+
+    keyed.into_iter().map(|(_key, item)| item).collect()
+}
+
+fn is_spending_category(y_values: &[f64]) -> bool {
+    median(y_values) > 0.0
+}
+
+pub fn is_significant_category(y_values: &[f64], threshold: f64) -> bool {
+    is_spending_category(y_values) && mean(y_values) >= threshold
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Palette {
+    Default,
+    Colorblind,
+    Pastel,
+    Dark,
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "default" => Ok(Palette::Default),
+            "colorblind" => Ok(Palette::Colorblind),
+            "pastel" => Ok(Palette::Pastel),
+            "dark" => Ok(Palette::Dark),
+            _ => Err(format!("Unknown palette '{}'", s)),
+        }
+    }
+}
+
+pub fn get_palette(palette: Palette) -> Vec<[u8; 3]> {
+    match palette {
+        Palette::Default => vec![
+            [31, 119, 180],
+            [255, 127, 14],
+            [44, 160, 44],
+            [214, 39, 40],
+            [148, 103, 189],
+            [140, 86, 75],
+            [227, 119, 194],
+            [127, 127, 127],
+            [188, 189, 34],
+            [23, 190, 207],
+        ],
+        // Wong, B. "Points of view: Color blindness", Nature Methods 8, 441 (2011)
+        Palette::Colorblind => vec![
+            [0, 0, 0],
+            [230, 159, 0],
+            [86, 180, 233],
+            [0, 158, 115],
+            [240, 228, 66],
+            [0, 114, 178],
+            [213, 94, 0],
+            [204, 121, 167],
+        ],
+        Palette::Pastel => vec![
+            [251, 180, 174],
+            [179, 205, 227],
+            [204, 235, 197],
+            [222, 203, 228],
+            [254, 217, 166],
+            [255, 255, 204],
+            [229, 216, 189],
+            [253, 218, 236],
+            [242, 242, 242],
+        ],
+        Palette::Dark => vec![
+            [27, 158, 119],
+            [217, 95, 2],
+            [117, 112, 179],
+            [231, 41, 138],
+            [102, 166, 30],
+            [230, 171, 2],
+            [166, 118, 29],
+            [102, 102, 102],
+        ],
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum CurrencyPosition {
+    Prefix,
+    Suffix,
+}
+
+impl FromStr for CurrencyPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "prefix" => Ok(CurrencyPosition::Prefix),
+            "suffix" => Ok(CurrencyPosition::Suffix),
+            _ => Err(format!("Unknown currency position '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CurrencyFormat {
+    pub symbol: String,
+    pub position: CurrencyPosition,
+    pub divisor: f64,
+}
+
+impl Default for CurrencyFormat {
+    fn default() -> Self {
+        CurrencyFormat {
+            symbol: String::from("₽"),
+            position: CurrencyPosition::Suffix,
+            divisor: 1000.0,
+        }
+    }
+}
+
+fn with_currency(amount: String, symbol: &str, position: CurrencyPosition) -> String {
+    match position {
+        CurrencyPosition::Prefix => format!("{}{}", symbol, amount),
+        CurrencyPosition::Suffix => format!("{} {}", amount, symbol),
+    }
+}
+
+pub fn format_currency(amount: f64, symbol: &str, position: CurrencyPosition) -> String {
+    let formatted = format!("{:.2}", amount.abs());
+    let (int_part, frac_part) = formatted.split_at(formatted.len() - 3);
+
+    let mut grouped = String::new();
+    if amount < 0.0 {
+        grouped.push('-');
+    }
+    for (i, c) in int_part.chars().enumerate() {
+        if i > 0 && (int_part.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    grouped.push_str(frac_part);
+
+    with_currency(grouped, symbol, position)
+}
+
+pub fn format_category_label(name: &str, avg: f64, currency: &CurrencyFormat) -> String {
+    let scaled = (avg / currency.divisor) as i64;
+    let amount = match currency.divisor as i64 {
+        1 => format_currency(avg, &currency.symbol, currency.position),
+        1000 => with_currency(format!("{}k", scaled), &currency.symbol, currency.position),
+        1_000_000 => with_currency(format!("{}M", scaled), &currency.symbol, currency.position),
+        _ => with_currency(scaled.to_string(), &currency.symbol, currency.position),
+    };
+    format!("{} (avg: {})", name, amount)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ChartType {
+    Line,
+    Bar,
+    Area,
+}
+
+impl FromStr for ChartType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "line" => Ok(ChartType::Line),
+            "bar" => Ok(ChartType::Bar),
+            "area" => Ok(ChartType::Area),
+            _ => Err(format!("Unknown chart type '{}'", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ColorMode {
+    Light,
+    Dark,
+}
+
+impl FromStr for ColorMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "light" => Ok(ColorMode::Light),
+            "dark" => Ok(ColorMode::Dark),
+            _ => Err(format!("Unknown color mode '{}'", s)),
+        }
+    }
+}
+
+fn color_mode_axis(mode: ColorMode) -> Axis {
+    match mode {
+        ColorMode::Light => Axis::new(),
+        ColorMode::Dark => Axis::new().grid_color(Rgb::new(68, 68, 68)),
+    }
+}
+
+pub fn apply_color_mode(layout: Layout, mode: ColorMode) -> Layout {
+    match mode {
+        ColorMode::Light => layout,
+        ColorMode::Dark => {
+            let [r, g, b] = DARK_MODE_FOREGROUND;
+            layout
+                .plot_background_color(Rgb::new(17, 17, 17))
+                .paper_background_color(Rgb::new(17, 17, 17))
+                .font(Font::new().color(Rgb::new(r, g, b)))
+                .legend(Legend::new().background_color(Rgb::new(34, 34, 34)))
+                .x_axis(color_mode_axis(mode))
+                .y_axis(color_mode_axis(mode))
+        }
+    }
+}
+
+pub fn palette_for_color_mode(palette: Palette, mode: ColorMode) -> Vec<[u8; 3]> {
+    let colors = get_palette(palette);
+    match mode {
+        ColorMode::Light => colors,
+        ColorMode::Dark => colors
+            .into_iter()
+            .map(|color| if color == [0, 0, 0] { DARK_MODE_FOREGROUND } else { color })
+            .collect(),
+    }
+}
+
+fn category_trace(
+    x_values: Vec<String>,
+    y_values: Vec<f64>,
+    label: &str,
+    color: Rgb,
+    chart_type: ChartType,
+) -> Box<dyn Trace> {
+    match chart_type {
+        ChartType::Line => Scatter::new(x_values, y_values)
+            .name(&fix_label(label))
+            .mode(Mode::LinesMarkers)
+            .line(Line::new().color(color)),
+        ChartType::Area => Scatter::new(x_values, y_values)
+            .name(&fix_label(label))
+            .mode(Mode::Lines)
+            .fill(Fill::ToZeroY)
+            .line(Line::new().color(color)),
+        ChartType::Bar => Bar::new(x_values, y_values)
+            .name(&fix_label(label))
+            .marker(Marker::new().color(color)),
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DrawConfig {
+    pub width: u32,
+    pub height: u32,
+    pub palette: Palette,
+    pub show_total: bool,
+    pub show_savings_rate: bool,
+    pub show_net_worth: bool,
+    pub initial_balance: f64,
+    pub missing_period_strategy: MissingPeriodStrategy,
+    pub sort_order: SortOrder,
+    pub currency: CurrencyFormat,
+    pub threshold: f64,
+    pub chart_type: ChartType,
+    pub annotate_anomalies: bool,
+    pub anomaly_sigma: f64,
+    pub group_by: GroupBy,
+    pub period_label_format: Option<String>,
+    pub show_forecast: bool,
+    pub budgets: BTreeMap<Category, f64>,
+    pub output_format: OutputFormat,
+    pub output_file: Option<PathBuf>,
+    pub output_dir: Option<PathBuf>,
+    pub hierarchy_separator: Option<char>,
+    pub sheet_names: Vec<String>,
+    pub sheet_aliases: HashMap<String, String>,
+    pub chart_title: Option<String>,
+    pub smoothing: Smoothing,
+    pub smoothing_window: usize,
+    pub show_year_boundaries: bool,
+    pub metric_name: String,
+    pub show_expense_ratio: bool,
+    pub income_category: Option<String>,
+    pub expense_ratio_target: Option<f64>,
+    pub color_mode: ColorMode,
+    pub inflation_base: Option<Period>,
+}
+
+impl Default for DrawConfig {
+    fn default() -> Self {
+        DrawConfig {
+            width: 1400,
+            height: 740,
+            palette: Palette::Default,
+            show_total: true,
+            show_savings_rate: false,
+            show_net_worth: false,
+            initial_balance: 0.0,
+            missing_period_strategy: MissingPeriodStrategy::Zero,
+            sort_order: SortOrder::Alpha,
+            currency: CurrencyFormat::default(),
+            threshold: 0.0,
+            chart_type: ChartType::Line,
+            annotate_anomalies: false,
+            anomaly_sigma: ANOMALY_SIGMA,
+            group_by: GroupBy::Month,
+            period_label_format: None,
+            show_forecast: false,
+            budgets: BTreeMap::new(),
+            output_format: OutputFormat::Browser,
+            output_file: None,
+            output_dir: None,
+            hierarchy_separator: None,
+            sheet_names: Vec::new(),
+            sheet_aliases: HashMap::new(),
+            chart_title: None,
+            smoothing: Smoothing::None,
+            smoothing_window: 3,
+            show_year_boundaries: false,
+            metric_name: String::from(DEFAULT_METRIC_NAME),
+            show_expense_ratio: false,
+            income_category: None,
+            expense_ratio_target: None,
+            color_mode: ColorMode::Light,
+            inflation_base: None,
+        }
+    }
+}
+
+impl DrawConfig {
+    pub fn validate(&self) -> Result<(), MyCustomError> {
+        if self.width < MIN_CHART_SIZE || self.height < MIN_CHART_SIZE {
+            return Err(MyCustomError::InvalidConfig(format!(
+                "chart size {}x{} is less than {} pixels",
+                self.width, self.height, MIN_CHART_SIZE
+            )));
+        }
+        if self.show_expense_ratio && self.income_category.is_none() {
+            return Err(MyCustomError::InvalidConfig(String::from(
+                "expense ratio requires an income category",
+            )));
+        }
+        Ok(())
+    }
+}
+
+pub fn add_year_boundary_shapes(
+    shapes: &mut Vec<Shape>,
+    annotations: &mut Vec<Annotation>,
+    period_meta: &[PeriodMeta],
+) {
+    for meta in period_meta.iter().filter(|meta| meta.is_year_start) {
+        shapes.push(
+            Shape::new()
+                .shape_type(ShapeType::Line)
+                .x_ref("x")
+                .y_ref("paper")
+                .x0(meta.label.clone())
+                .x1(meta.label.clone())
+                .y0(0.0)
+                .y1(1.0)
+                .line(ShapeLine::new().color(Rgb::new(128, 128, 128)).dash(DashType::Dash)),
+        );
+        annotations.push(
+            Annotation::new()
+                .x(meta.label.clone())
+                .y_ref("paper")
+                .y(1.0)
+                .text(&meta.year.to_string())
+                .show_arrow(false),
+        );
+    }
+}
+
+pub fn plot(
+    title: String,
+    worksheet_data: &WorksheetData,
+    periods: &[Period],
+    config: &DrawConfig,
+) -> Result<Plot, MyCustomError> {
+    if periods.is_empty() {
+        return Err(MyCustomError::EmptyPeriods);
+    }
+
+    let period_label = |period: &Period| match &config.period_label_format {
+        Some(format) => reformat_period_label(period, config.group_by.clone(), format),
+        None => period.to_string(),
+    };
+    let x_values: Vec<String> = periods.iter().map(&period_label).collect();
+
+    let mut plot = Plot::new();
+    let mut layout = apply_color_mode(Layout::new(), config.color_mode)
+        .title(Title::new(&fix_label(&title)))
+        .width(config.width as usize)
+        .height(config.height as usize);
+    if let ChartType::Bar = config.chart_type {
+        layout = layout.bar_mode(BarMode::Stack);
+    }
+    let percent_axis = config.show_savings_rate || config.show_expense_ratio;
+    if percent_axis {
+        layout = layout.y_axis2(
+            Axis::new()
+                .title(Title::new("%"))
+                .overlaying("y")
+                .side(AxisSide::Right),
+        );
+    }
+    let net_worth_axis = if percent_axis { "y3" } else { "y2" };
+    if config.show_net_worth {
+        let axis = Axis::new()
+            .title(Title::new(&config.currency.symbol))
+            .overlaying("y")
+            .side(AxisSide::Right);
+        layout = if percent_axis {
+            layout
+                .x_axis(color_mode_axis(config.color_mode).domain(&[0.0, THIRD_AXIS_X_DOMAIN_END]))
+                .y_axis3(axis.anchor("free").position(1.0))
+        } else {
+            layout.y_axis2(axis)
+        };
+    }
+    let mut annotations = Vec::new();
+    if config.annotate_anomalies {
+        annotations.extend(
+            detect_anomalies(worksheet_data, periods, config.anomaly_sigma)
+                .into_iter()
+                .map(|anomaly| {
+                    Annotation::new()
+                        .x(period_label(&anomaly.period))
+                        .y(anomaly.value)
+                        .text(&fix_label(&format!(
+                            "{} ({:+.1}σ)",
+                            anomaly.category, anomaly.z_score
+                        )))
+                        .show_arrow(true)
+                }),
+        );
+    }
+
+    let palette = palette_for_color_mode(config.palette, config.color_mode);
+    let mut colors = palette.iter().cycle();
+    let rgb = |[r, g, b]: &[u8; 3]| Rgb::new(*r, *g, *b);
+    let forecast = if config.show_forecast {
+        forecast_next_period(worksheet_data, periods)
+    } else {
+        BTreeMap::new()
+    };
+
+    let mut y_total : Vec<f64> = Vec::new();
+    for _ in periods.iter() {
+        y_total.push(0.0);
+    }
+
+    let mut shapes = Vec::new();
+    if config.show_year_boundaries {
+        let period_meta: Vec<PeriodMeta> = extract_period_metadata(periods, config.group_by.clone())
+            .into_iter()
+            .map(|meta| PeriodMeta {
+                label: period_label(&Period::from(meta.period.as_str())),
+                ..meta
+            })
+            .collect();
+        add_year_boundary_shapes(&mut shapes, &mut annotations, &period_meta);
+    }
+    let mut has_spendings = false;
+    for (cat, by_cat) in sort_categories(worksheet_data, periods, config.sort_order) {
+        let y_values = y(by_cat, &periods);
+        if is_significant_category(&y_values, config.threshold) {
+            let parent = config
+                .hierarchy_separator
+                .and_then(|separator| cat.split_once(separator))
+                .map(|(parent, _child)| parent);
+            if let Some(parent) = parent {
+                let y_values = smooth(
+                    y_with_strategy(by_cat, periods, config.missing_period_strategy),
+                    config.smoothing,
+                    config.smoothing_window,
+                );
+                plot.add_trace(
+                    Scatter::new(x_values.clone(), y_values)
+                        .name(&fix_label(cat))
+                        .mode(Mode::LinesMarkers)
+                        .legend_group(parent)
+                        .visible(Visible::LegendOnly)
+                        .line(Line::new().dash(DashType::Dash).color(rgb(colors.next().unwrap()))),
+                );
+                continue;
+            }
+            has_spendings = true;
+            for it in y_values.iter().zip(y_total.iter_mut()) {
+                let (v, t) = it;
+                *t += *v;
+            }
+            let label = format_category_label(cat, mean(&y_values), &config.currency);
+            let color = colors.next().unwrap();
+            if let Some(budget) = config.budgets.get(cat) {
+                shapes.push(
+                    Shape::new()
+                        .shape_type(ShapeType::Line)
+                        .x_ref("x")
+                        .y_ref("y")
+                        .x0(x_values[0].clone())
+                        .x1(x_values[x_values.len() - 1].clone())
+                        .y0(*budget)
+                        .y1(*budget)
+                        .line(ShapeLine::new().color(rgb(color)).dash(DashType::Dash)),
+                );
+            }
+            if let Some(next) = forecast.get(cat) {
+                plot.add_trace(
+                    Scatter::new(
+                        vec![x_values[x_values.len() - 1].clone(), String::from("forecast")],
+                        vec![y_values[y_values.len() - 1], *next],
+                    )
+                    .name(&fix_label(&label))
+                    .mode(Mode::LinesMarkers)
+                    .show_legend(false)
+                    .line(Line::new().dash(DashType::Dot).color(rgb(color))),
+                );
+            }
+            let y_values = smooth(
+                y_with_strategy(by_cat, periods, config.missing_period_strategy),
+                config.smoothing,
+                config.smoothing_window,
+            );
+            plot.add_trace(category_trace(
+                x_values.clone(),
+                y_values,
+                &label,
+                rgb(color),
+                config.chart_type,
+            ));
+        }
+    }
+
+    if !has_spendings {
+        return Err(MyCustomError::NoSpendingCategories);
+    }
+
+    if config.show_total {
+        let label = format_category_label("Всего", mean(&y_total), &config.currency);
+        plot.add_trace(category_trace(
+            x_values.clone(),
+            y_total,
+            &label,
+            rgb(colors.next().unwrap()),
+            ChartType::Line,
+        ));
+    }
+
+    if config.show_savings_rate {
+        let (rate_x, rate_y): (Vec<_>, Vec<_>) = x_values
+            .iter()
+            .cloned()
+            .zip(compute_savings_rate(worksheet_data, periods))
+            .filter(|(_period, rate)| !rate.is_nan())
+            .unzip();
+        plot.add_trace(
+            Scatter::new(rate_x, rate_y)
+                .name(&fix_label("Норма сбережений, %"))
+                .mode(Mode::LinesMarkers)
+                .y_axis("y2")
+                .line(Line::new().dash(DashType::Dot).color(rgb(colors.next().unwrap()))),
+        );
+    }
+
+    if let (true, Some(income_category)) = (config.show_expense_ratio, &config.income_category) {
+        let expense_categories: Vec<&str> = worksheet_data
+            .iter()
+            .filter(|(cat, by_cat)| {
+                cat.as_str() != income_category.as_str() && y(by_cat, periods).iter().sum::<f64>() > 0.0
+            })
+            .map(|(cat, _by_cat)| cat.as_str())
+            .collect();
+        let ratio =
+            compute_expense_ratio(worksheet_data, income_category, &expense_categories, periods);
+        let (ratio_x, ratio_y): (Vec<_>, Vec<_>) = x_values
+            .iter()
+            .cloned()
+            .zip(ratio)
+            .filter(|(_period, ratio)| !ratio.is_nan())
+            .map(|(period, ratio)| (period, ratio * 100.0))
+            .unzip();
+        plot.add_trace(
+            Scatter::new(ratio_x, ratio_y)
+                .name(&fix_label("Доля расходов, %"))
+                .mode(Mode::LinesMarkers)
+                .y_axis("y2")
+                .line(Line::new().dash(DashType::DashDot).color(rgb(colors.next().unwrap()))),
+        );
+        if let Some(target) = config.expense_ratio_target {
+            shapes.push(
+                Shape::new()
+                    .shape_type(ShapeType::Line)
+                    .x_ref("paper")
+                    .y_ref("y2")
+                    .x0(0.0)
+                    .x1(1.0)
+                    .y0(target * 100.0)
+                    .y1(target * 100.0)
+                    .line(ShapeLine::new().color(Rgb::new(128, 128, 128)).dash(DashType::Dot)),
+            );
+        }
+    }
+
+    if config.show_net_worth {
+        let (income, expenses) = split_income_expense(worksheet_data, periods);
+        let net_worth =
+            compute_cumulative_net_worth(&income, &expenses, periods, config.initial_balance);
+        plot.add_trace(
+            Scatter::new(x_values.clone(), net_worth)
+                .name(&fix_label("Капитал"))
+                .mode(Mode::LinesMarkers)
+                .y_axis(net_worth_axis)
+                .line(Line::new().color(rgb(colors.next().unwrap()))),
+        );
+    }
+
+    if !shapes.is_empty() {
+        layout = layout.shapes(shapes);
+    }
+    if !annotations.is_empty() {
+        layout = layout.annotations(annotations);
+    }
+    plot.set_layout(layout);
+
+    Ok(plot)
+}
+
+pub fn format_chart_title(template: &str, sheet_name: &str) -> String {
+    template.replace("{sheet}", sheet_name)
+}
+
+pub fn apply_sheet_aliases<'a>(name: &'a str, aliases: &'a HashMap<String, String>) -> &'a str {
+    match aliases.get(name) {
+        Some(alias) => alias,
+        None => name,
+    }
+}
+
+fn chart_title(config: &DrawConfig, sheet: usize) -> String {
+    let sheet_name = config
+        .sheet_names
+        .get(sheet)
+        .map(|name| apply_sheet_aliases(name, &config.sheet_aliases));
+    let title = match (&config.chart_title, sheet_name) {
+        (Some(template), name) => format_chart_title(template, name.unwrap_or_default()),
+        (None, Some(name)) => format!("Все траты: {}", name),
+        (None, None) => String::from("Все траты"),
+    };
+    match &config.inflation_base {
+        Some(base) => format!("{} (в ценах {})", title, base),
+        None => title,
+    }
+}
+
+pub fn draw(
+    data: Vec<WorksheetData>,
+    periods: &[Period],
+    config: &DrawConfig,
+) -> Result<(), MyCustomError> {
+    if let OutputFormat::Ndjson = config.output_format {
+        let mut writer: Box<dyn Write> = match &config.output_file {
+            Some(path) => Box::new(fs::File::create(path)?),
+            None => Box::new(io::stdout()),
+        };
+        return export_ndjson(&data, periods, &mut writer);
+    }
+
+    if let OutputFormat::Prometheus = config.output_format {
+        let mut writer: Box<dyn Write> = match &config.output_file {
+            Some(path) => Box::new(fs::File::create(path)?),
+            None => Box::new(io::stdout()),
+        };
+        let merged = merge_worksheet_data(data);
+        return export_prometheus_metrics_with_name(&merged, periods, &config.metric_name, &mut writer);
+    }
+
+    if let OutputFormat::Text = config.output_format {
+        let mut writer: Box<dyn Write> = match &config.output_file {
+            Some(path) => Box::new(fs::File::create(path)?),
+            None => Box::new(io::stdout()),
+        };
+        for (sheet, worksheet_data) in data.iter().enumerate() {
+            if let Some(name) = config.sheet_names.get(sheet) {
+                writeln!(writer, "{}", apply_sheet_aliases(name, &config.sheet_aliases))?;
+            }
+            export_plain_text_report(worksheet_data, periods, &mut writer)?;
+            writeln!(writer)?;
+        }
+        return Ok(());
+    }
+
+    if let OutputFormat::Gnuplot = config.output_format {
+        let dir = config.output_dir.clone().unwrap_or_else(|| PathBuf::from("."));
+        let file_name = |path: &Path| path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        for (sheet, worksheet_data) in data.iter().enumerate() {
+            let dat = sheet_output_path(&dir.join("data.dat"), sheet);
+            let script = sheet_output_path(&dir.join("plot.gnu"), sheet);
+            let chart = sheet_output_path(&dir.join("chart.svg"), sheet);
+            export_gnuplot_dat(worksheet_data, periods, &mut fs::File::create(&dat)?)?;
+            write_gnuplot_script(
+                worksheet_data,
+                &chart_title(config, sheet),
+                &file_name(&dat),
+                &file_name(&chart),
+                config,
+                &mut fs::File::create(&script)?,
+            )?;
+            info!(sheet = sheet, path = %script.display(), "gnuplot script saved");
+        }
+        return Ok(());
+    }
+
+    let mut drawn = 0;
+    for (sheet, worksheet_data) in data.into_iter().enumerate() {
+        match plot(chart_title(config, sheet), &worksheet_data, periods, config) {
+            Ok(mut plot) => {
+                match config.output_format {
+                    OutputFormat::Browser => plot.show(),
+                    OutputFormat::PlotlyHtml => {
+                        let path = match (&config.output_file, &config.output_dir) {
+                            (Some(path), _) => sheet_output_path(path, sheet),
+                            (None, Some(dir)) => generate_output_path(dir, ".html"),
+                            (None, None) => generate_output_path(Path::new("."), ".html"),
+                        };
+                        save_plot_html(&mut plot, &path)?;
+                        info!(sheet = sheet, path = %path.display(), "chart saved");
+                    }
+                    OutputFormat::Ndjson
+                    | OutputFormat::Gnuplot
+                    | OutputFormat::Prometheus
+                    | OutputFormat::Text => unreachable!(),
+                }
+                drawn += 1;
+            }
+            Err(MyCustomError::NoSpendingCategories) => {
+                warn!(sheet = sheet, "no spending categories, skipping chart");
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    if drawn == 0 {
+        return Err(MyCustomError::NoSpendingCategories);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone)]
+pub struct ReportConfig {
+    pub top_n: usize,
+    pub anomaly_sigma: f64,
+    pub budgets: BTreeMap<Category, f64>,
+    pub color_mode: ColorMode,
+    pub currency: CurrencyFormat,
+    pub periods: Vec<Period>,
+    pub sheet_names: Vec<String>,
+    pub sheet_aliases: HashMap<String, String>,
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        ReportConfig {
+            top_n: 5,
+            anomaly_sigma: ANOMALY_SIGMA,
+            budgets: BTreeMap::new(),
+            color_mode: ColorMode::Light,
+            currency: CurrencyFormat::default(),
+            periods: Vec::new(),
+            sheet_names: Vec::new(),
+            sheet_aliases: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PieTrace {
+    #[serde(rename = "type")]
+    trace_type: &'static str,
+    name: String,
+    labels: Vec<String>,
+    values: Vec<f64>,
+}
+
+impl PieTrace {
+    fn new(labels: Vec<String>, values: Vec<f64>) -> Box<Self> {
+        Box::new(PieTrace {
+            trace_type: "pie",
+            name: String::new(),
+            labels,
+            values,
+        })
+    }
+
+    fn name(mut self: Box<Self>, name: &str) -> Box<Self> {
+        self.name = name.to_string();
+        self
+    }
+}
+
+impl Trace for PieTrace {
+    fn serialize(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
+
+fn plotly_script() -> Result<String, MyCustomError> {
+    let mut plot = Plot::new();
+    plot.use_local_plotly();
+    let html = plot.to_html();
+
+    let missing = || MyCustomError::HtmlExportError(String::from("plotly.js is not bundled"));
+    let start = html.find("<script").ok_or_else(missing)?;
+    let end = html[start..].find("</script>").ok_or_else(missing)? + start + "</script>".len();
+    let script = &html[start..end];
+    let open_tag = &script[..script.find('>').unwrap_or(script.len())];
+    if open_tag.contains("src=") {
+        return Err(missing());
+    }
+    Ok(script.to_string())
+}
+
+fn top_categories_plot(data: &WorksheetData, periods: &[Period], config: &ReportConfig) -> Plot {
+    let mut totals: Vec<(String, f64)> = data
+        .iter()
+        .map(|(cat, by_cat)| (fix_label(cat), y(by_cat, periods).iter().sum::<f64>()))
+        .filter(|(_cat, total)| *total > 0.0)
+        .collect();
+    totals.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+    totals.truncate(config.top_n);
+    let (names, values): (Vec<_>, Vec<_>) = totals.into_iter().unzip();
+
+    let mut plot = Plot::new();
+    plot.add_trace(PieTrace::new(names, values).name(&fix_label("Всего")));
+    plot.set_layout(apply_color_mode(Layout::new(), config.color_mode));
+    plot
+}
+
+pub fn generate_full_report(
+    data: Vec<WorksheetData>,
+    config: &ReportConfig,
+    output_path: &Path,
+) -> Result<(), MyCustomError> {
+    let periods = if config.periods.is_empty() {
+        worksheet_data_to_periods(&data)
+    } else {
+        config.periods.clone()
+    };
+    let money = |amount: f64| {
+        format_currency(amount, &config.currency.symbol, config.currency.position)
+    };
+    let draw_config = DrawConfig {
+        show_savings_rate: true,
+        annotate_anomalies: true,
+        anomaly_sigma: config.anomaly_sigma,
+        budgets: config.budgets.clone(),
+        color_mode: config.color_mode,
+        currency: config.currency.clone(),
+        ..DrawConfig::default()
+    };
+    let (background, foreground) = match config.color_mode {
+        ColorMode::Light => ("#ffffff", "#222222"),
+        ColorMode::Dark => ("#111111", "#e6e6e6"),
+    };
+
+    let mut html: Vec<u8> = Vec::new();
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(html, "<html><head><meta charset=\"utf-8\"><title>Отчёт</title>")?;
+    writeln!(html, "{}", plotly_script()?)?;
+    writeln!(
+        html,
+        "<style>body {{ font-family: sans-serif; margin: 2em; background: {}; color: {}; }}</style>",
+        background, foreground
+    )?;
+    writeln!(html, "</head><body>")?;
+
+    for (sheet, worksheet_data) in data.iter().enumerate() {
+        let title = match config.sheet_names.get(sheet) {
+            Some(name) => apply_sheet_aliases(name, &config.sheet_aliases).to_string(),
+            None => format!("Лист {}", sheet + 1),
+        };
+        writeln!(html, "<h1>{}</h1>", html_escape(&title))?;
+
+        let summary = worksheet_data_summary(worksheet_data, &periods);
+        writeln!(html, "<h2>Сводка</h2><ul>")?;
+        writeln!(html, "<li>Периодов: {}</li>", summary.total_periods)?;
+        writeln!(html, "<li>Категорий: {}</li>", summary.total_categories)?;
+        writeln!(html, "<li>Доходы: {}</li>", money(summary.total_income))?;
+        writeln!(html, "<li>Расходы: {}</li>", money(summary.total_expense))?;
+        writeln!(html, "<li>Итого: {}</li>", money(summary.net))?;
+        writeln!(html, "<li>Крупнейшая категория: {}</li>", html_escape(&summary.top_category))?;
+        writeln!(html, "</ul>")?;
+
+        writeln!(html, "<h2>Траты</h2>")?;
+        match plot(String::from("Все траты"), worksheet_data, &periods, &draw_config) {
+            Ok(plot) => {
+                let div_id = format!("spending-{}", sheet);
+                writeln!(html, "{}", plot.to_inline_html(Some(&div_id)))?
+            }
+            Err(MyCustomError::NoSpendingCategories) => writeln!(html, "<p>Нет трат</p>")?,
+            Err(e) => return Err(e),
+        }
+
+        writeln!(html, "<h2>Крупнейшие категории</h2>")?;
+        let top = top_categories_plot(worksheet_data, &periods, config);
+        writeln!(html, "{}", top.to_inline_html(Some(&format!("top-{}", sheet))))?;
+
+        if periods.len() > 1 {
+            writeln!(html, "<h2>Изменения к прошлому периоду, %</h2>")?;
+            let delta = compute_period_over_period_delta(worksheet_data, &periods);
+            export_html_table(&delta, &periods[1..], &mut html)?;
+        }
+
+        writeln!(html, "<h2>Аномалии</h2><ul>")?;
+        for anomaly in detect_anomalies(worksheet_data, &periods, config.anomaly_sigma) {
+            writeln!(
+                html,
+                "<li>{} {}: {} ({:+.1}σ)</li>",
+                html_escape(&anomaly.category),
+                html_escape(&anomaly.period),
+                money(anomaly.value),
+                anomaly.z_score
+            )?;
+        }
+        writeln!(html, "</ul>")?;
+
+        writeln!(html, "<h2>Норма сбережений</h2><ul>")?;
+        for (period, rate) in periods.iter().zip(compute_savings_rate(worksheet_data, &periods)) {
+            if !rate.is_nan() {
+                writeln!(html, "<li>{}: {:.1}%</li>", html_escape(period), rate)?;
+            }
+        }
+        writeln!(html, "</ul>")?;
+    }
+
+    writeln!(html, "</body></html>")?;
+    fs::write(output_path, html).map_err(|e| MyCustomError::HtmlExportError(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn periods(names: &[&str]) -> Vec<Period> {
+        names.iter().map(|name| Period::from(*name)).collect()
+    }
+
+    fn worksheet(entries: &[(&str, &str, f64)]) -> WorksheetData {
+        let mut data: WorksheetData = BTreeMap::new();
+        for (cat, period, value) in entries {
+            data.entry(Category::from(*cat))
+                .or_insert_with(BTreeMap::new)
+                .insert(Period::from(*period), *value);
+        }
+        data
+    }
+
+    #[test]
+    fn export_csv_writes_category_rows_and_period_columns() {
+        let data = worksheet(&[
+            ("Еда", "2021-01", 100.0),
+            ("Еда", "2021-02", 12.5),
+            ("Связь", "2021-02", 7.0),
+        ]);
+        let mut out = Vec::new();
+        export_csv(&data, &periods(&["2021-01", "2021-02"]), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Category,2021-01,2021-02\nЕда,100,12.5\nСвязь,0,7\n"
+        );
+    }
+
+    #[test]
+    fn export_csv_multi_prepends_sheet_column() {
+        let sheets = vec![
+            worksheet(&[("Еда", "2021-01", 100.0)]),
+            worksheet(&[("Связь", "2021-01", 7.0)]),
+        ];
+        let mut out = Vec::new();
+        export_csv_multi(&sheets, &periods(&["2021-01"]), &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Sheet,Category,2021-01\n0,Еда,100\n1,Связь,7\n"
+        );
+    }
+
+    fn twelve_months() -> Vec<Period> {
+        (1..=12).map(|month| Period::from(format!("2021-{:02}", month))).collect()
+    }
+
+    #[test]
+    fn recurring_categories_split_by_presence_threshold() {
+        let months = twelve_months();
+        let mut data = WorksheetData::new();
+        data.insert(
+            Category::from("Аренда"),
+            months.iter().skip(1).map(|period| (period.clone(), 30000.0)).collect(),
+        );
+        data.insert(
+            Category::from("Отпуск"),
+            months.iter().take(2).map(|period| (period.clone(), 50000.0)).collect(),
+        );
+
+        assert_eq!(
+            detect_recurring_categories(&data, &months, RECURRING_THRESHOLD),
+            vec![Category::from("Аренда")]
+        );
+        assert_eq!(
+            detect_irregular_categories(&data, &months, RECURRING_THRESHOLD),
+            vec![Category::from("Отпуск")]
+        );
+    }
+
+    #[test]
+    fn trim_category_names_merges_collisions() {
+        let data = worksheet(&[
+            ("Еда", "2021-01", 100.0),
+            (" Еда", "2021-01", 50.0),
+            ("Еда ", "2021-02", 20.0),
+        ]);
+        assert_eq!(
+            trim_category_names(data),
+            worksheet(&[("Еда", "2021-01", 150.0), ("Еда", "2021-02", 20.0)])
+        );
+    }
+
+    #[test]
+    fn lowercase_category_names_merges_collisions() {
+        let data = worksheet(&[("Еда", "2021-01", 100.0), ("еда", "2021-01", 50.0)]);
+        assert_eq!(
+            lowercase_category_names(data),
+            worksheet(&[("еда", "2021-01", 150.0)])
+        );
+    }
+
+    #[test]
+    fn sort_categories_mean_desc_puts_highest_mean_first() {
+        let data = worksheet(&[
+            ("Аренда", "2021-01", 30000.0),
+            ("Аренда", "2021-02", 30000.0),
+            ("Еда", "2021-01", 12000.0),
+            ("Еда", "2021-02", 14000.0),
+            ("Связь", "2021-02", 700.0),
+        ]);
+        let order: Vec<&str> = sort_categories(
+            &data,
+            &periods(&["2021-01", "2021-02"]),
+            SortOrder::MeanDesc,
+        )
+        .into_iter()
+        .map(|(cat, _by_cat)| cat.as_str())
+        .collect();
+        assert_eq!(order, vec!["Аренда", "Еда", "Связь"]);
+    }
+
+    #[test]
+    fn sort_categories_mean_asc_reverses_mean_desc() {
+        let data = worksheet(&[("Аренда", "2021-01", 30000.0), ("Еда", "2021-01", 12000.0)]);
+        let order: Vec<&str> = sort_categories(&data, &periods(&["2021-01"]), SortOrder::MeanAsc)
+            .into_iter()
+            .map(|(cat, _by_cat)| cat.as_str())
+            .collect();
+        assert_eq!(order, vec!["Еда", "Аренда"]);
+    }
+
+    #[test]
+    fn is_significant_category_applies_mean_threshold() {
+        let values = [400.0, 500.0, 600.0];
+        assert!(!is_significant_category(&values, 1000.0));
+        assert!(is_significant_category(&values, 100.0));
+        assert!(is_significant_category(&values, 0.0));
+    }
+
+    #[test]
+    fn compute_growth_rate_of_doubling_series_is_one_hundred_percent() {
+        let rate = compute_growth_rate(&[100.0, 200.0, 400.0, 800.0, 1600.0]);
+        assert!((rate - 1.0).abs() < 1e-9, "rate = {}", rate);
+    }
+
+    #[test]
+    fn compute_growth_rate_needs_two_values() {
+        assert_eq!(compute_growth_rate(&[]), 0.0);
+        assert_eq!(compute_growth_rate(&[100.0]), 0.0);
+    }
+
+    #[test]
+    fn generate_output_path_is_unique_per_call() {
+        let dir = Path::new("charts");
+        let first = generate_output_path(dir, ".png");
+        let second = generate_output_path(dir, ".png");
+        assert_ne!(first, second);
+        assert_eq!(first.parent(), Some(dir));
+        assert_eq!(first.extension().and_then(|ext| ext.to_str()), Some("png"));
+    }
+
+    fn columns() -> Columns {
+        Columns {
+            period: 0,
+            category: 1,
+            tx_type: 2,
+            value: 3,
+        }
+    }
+
+    fn string_cell(s: &str) -> DataType {
+        DataType::String(s.to_string())
+    }
+
+    fn row(period: DataType, value: DataType) -> Vec<DataType> {
+        vec![period, string_cell("Еда"), string_cell("Расход"), value]
+    }
+
+    fn read_value(value: DataType) -> Result<f64, RowParseError> {
+        let row = row(string_cell("15.03.2021"), value);
+        read_row_with_index(&columns(), &row, 1, &TxTypeLabels::default()).map(|fields| fields.value)
+    }
+
+    #[test]
+    fn read_row_accepts_int_value_cells() {
+        assert_eq!(read_value(DataType::Int(1500)).unwrap(), 1500.0);
+    }
+
+    #[test]
+    fn read_row_parses_string_value_cells() {
+        assert_eq!(read_value(string_cell(" 99.5 ")).unwrap(), 99.5);
+        let err = read_value(string_cell("сто")).unwrap_err();
+        assert!(matches!(err.field, ParseField::Value));
+        assert_eq!(err.raw_value, "сто");
+    }
+
+    #[test]
+    fn read_row_treats_empty_value_cells_as_zero() {
+        assert_eq!(read_value(DataType::Empty).unwrap(), 0.0);
+    }
+
+    fn range(rows: &[Vec<DataType>]) -> Range<DataType> {
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0) as u32;
+        let mut range = Range::new((0, 0), (rows.len() as u32 - 1, width - 1));
+        for (r, row) in rows.iter().enumerate() {
+            for (c, cell) in row.iter().enumerate() {
+                range.set_value((r as u32, c as u32), cell.clone());
+            }
+        }
+        range
+    }
+
+    fn header_row() -> Vec<DataType> {
+        let headers = ColumnHeaders::default();
+        vec![
+            string_cell(&headers.period),
+            string_cell(&headers.category),
+            string_cell(&headers.tx_type),
+            string_cell(&headers.value),
+        ]
+    }
+
+    fn tx_row(date: &str, category: &str, tx_type: &str, value: f64) -> Vec<DataType> {
+        vec![
+            string_cell(date),
+            string_cell(category),
+            string_cell(tx_type),
+            DataType::Float(value),
+        ]
+    }
+
+    fn read_range(rows: &[Vec<DataType>]) -> Result<WorksheetData, MyCustomError> {
+        read_worksheet_from_range(
+            String::from("Лист1"),
+            range(rows),
+            by_month,
+            ColumnHeaders::default(),
+        )
+    }
+
+    #[test]
+    fn read_worksheet_from_range_aggregates_rows_by_period() {
+        let data = read_range(&[
+            header_row(),
+            tx_row("15.01.2021", "Еда", "Расход", 100.0),
+            tx_row("20.01.2021", "Еда", "Расход", 50.0),
+            tx_row("03.02.2021", "Еда", "Расход", 30.0),
+            tx_row("10.01.2021", "Зарплата", "Доход", 1000.0),
+            tx_row("11.01.2021", "Сбережения", "Перевод", 500.0),
+        ])
+        .unwrap();
+        assert_eq!(
+            data,
+            worksheet(&[
+                ("Еда", "2021-01", 150.0),
+                ("Еда", "2021-02", 30.0),
+                ("Зарплата", "2021-01", -1000.0),
+            ])
+        );
+    }
+
+    #[test]
+    fn read_worksheet_from_range_skips_bad_dates() {
+        let data = read_range(&[
+            header_row(),
+            tx_row("2021/01/15", "Еда", "Расход", 100.0),
+            tx_row("31.02.2021", "Еда", "Расход", 100.0),
+            tx_row("15.01.2021", "Еда", "Расход", 40.0),
+        ])
+        .unwrap();
+        assert_eq!(data, worksheet(&[("Еда", "2021-01", 40.0)]));
+    }
+
+    #[test]
+    fn read_worksheet_from_range_skips_unknown_tx_types() {
+        let data = read_range(&[
+            header_row(),
+            tx_row("15.01.2021", "Еда", "Возврат", 100.0),
+            tx_row("16.01.2021", "Связь", "Расход", 7.0),
+        ])
+        .unwrap();
+        assert_eq!(data, worksheet(&[("Связь", "2021-01", 7.0)]));
+    }
+
+    #[test]
+    fn read_worksheet_from_range_handles_empty_sheets() {
+        let empty = read_worksheet_from_range(
+            String::from("Лист1"),
+            Range::empty(),
+            by_month,
+            ColumnHeaders::default(),
+        );
+        assert!(matches!(empty, Err(MyCustomError::OtherError)));
+
+        assert_eq!(read_range(&[header_row()]).unwrap(), WorksheetData::new());
+    }
+
+    #[test]
+    fn read_worksheet_from_range_uses_custom_headers() {
+        let headers = ColumnHeaders {
+            period: String::from("Date"),
+            category: String::from("Category"),
+            tx_type: String::from("Type"),
+            value: String::from("Amount"),
+        };
+        let rows = vec![
+            vec![
+                string_cell("Date"),
+                string_cell("Category"),
+                string_cell("Type"),
+                string_cell("Amount"),
+            ],
+            tx_row("15.01.2021", "Еда", "Расход", 100.0),
+        ];
+        let data =
+            read_worksheet_from_range(String::from("Sheet1"), range(&rows), by_month, headers)
+                .unwrap();
+        assert_eq!(data, worksheet(&[("Еда", "2021-01", 100.0)]));
+    }
+
+    #[test]
+    fn budgets_per_period_scales_monthly_budgets() {
+        let mut budgets = BTreeMap::new();
+        budgets.insert(Category::from("Еда"), 10000.0);
+
+        let quarterly = budgets_per_period(budgets.clone(), &GroupBy::Quarter).unwrap();
+        assert_eq!(quarterly[&Category::from("Еда")], 30000.0);
+        let yearly = budgets_per_period(budgets.clone(), &GroupBy::Year).unwrap();
+        assert_eq!(yearly[&Category::from("Еда")], 120000.0);
+        let monthly = budgets_per_period(budgets.clone(), &GroupBy::Month).unwrap();
+        assert_eq!(monthly[&Category::from("Еда")], 10000.0);
+        let rolling = budgets_per_period(budgets.clone(), &GroupBy::RollingQuarter).unwrap();
+        assert_eq!(rolling[&Category::from("Еда")], 30000.0);
+        assert!(budgets_per_period(budgets, &GroupBy::WeekOfMonth).is_err());
+    }
+
+    fn read_period(period: DataType) -> Result<NaiveDate, RowParseError> {
+        let row = row(period, DataType::Float(1.0));
+        read_row_with_index(&columns(), &row, 1, &TxTypeLabels::default()).map(|fields| fields.period)
+    }
+
+    #[test]
+    fn read_row_converts_datetime_serials() {
+        assert_eq!(
+            read_period(DataType::DateTime(45123.0)).unwrap(),
+            NaiveDate::from_ymd(2023, 7, 16)
+        );
+        assert_eq!(
+            read_period(DataType::DateTime(45123.75)).unwrap(),
+            NaiveDate::from_ymd(2023, 7, 16)
+        );
+        assert!(read_period(DataType::DateTime(-1.0)).is_err());
+    }
+
+    #[test]
+    fn read_row_still_parses_string_dates() {
+        assert_eq!(
+            read_period(string_cell("16.07.2023")).unwrap(),
+            NaiveDate::from_ymd(2023, 7, 16)
+        );
+    }
+
+    #[test]
+    fn infer_columns_heuristically_skips_leading_rows() {
+        let metadata = |text: &str| {
+            vec![
+                string_cell(text),
+                string_cell("Расход"),
+                string_cell(""),
+                string_cell("01.01.2021"),
+            ]
+        };
+        let rows = vec![
+            metadata("Выписка по счёту"),
+            metadata("Период выписки"),
+            vec![
+                string_cell("Сумма"),
+                string_cell("Дата"),
+                string_cell("Тип"),
+                string_cell("Описание"),
+            ],
+            vec![
+                DataType::Float(100.0),
+                string_cell("15.01.2021"),
+                string_cell("Расход"),
+                string_cell("Еда"),
+            ],
+            vec![
+                DataType::Int(7),
+                string_cell("16.01.2021"),
+                string_cell("Расход"),
+                string_cell("Связь"),
+            ],
+        ];
+        let options = ParseOptions {
+            skip_rows: 2,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            infer_columns_heuristically(&range(&rows), &options),
+            Some(Columns {
+                period: 1,
+                category: 3,
+                tx_type: 2,
+                value: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn save_plot_html_writes_standalone_document() {
+        let mut plot = Plot::new();
+        plot.add_trace(Scatter::new(vec!["2021-01", "2021-02"], vec![1.0, 2.0]));
+        let path = generate_output_path(&std::env::temp_dir(), ".html");
+        save_plot_html(&mut plot, &path).unwrap();
+        let html = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(html.trim_start().to_lowercase().starts_with("<!doctype html>"));
+        assert!(!html.contains("<script src=\"https://cdn.plot.ly"));
+    }
+
+    #[test]
+    fn period_over_period_delta_of_known_series() {
+        let months = periods(&["2021-01", "2021-02", "2021-03", "2021-04"]);
+        let data = worksheet(&[
+            ("Еда", "2021-01", 100.0),
+            ("Еда", "2021-02", 150.0),
+            ("Еда", "2021-03", 0.0),
+            ("Еда", "2021-04", 30.0),
+        ]);
+
+        let pct = &compute_period_over_period_delta(&data, &months)[&Category::from("Еда")];
+        assert!(!pct.contains_key(&Period::from("2021-01")));
+        assert_eq!(pct[&Period::from("2021-02")], 50.0);
+        assert_eq!(pct[&Period::from("2021-03")], -100.0);
+        assert!(pct[&Period::from("2021-04")].is_nan());
+
+        let abs = &compute_period_over_period_abs_delta(&data, &months)[&Category::from("Еда")];
+        assert_eq!(abs.values().cloned().collect::<Vec<f64>>(), vec![50.0, -150.0, 30.0]);
+    }
+
+    #[test]
+    fn group_by_parses_known_names() {
+        assert_eq!("month".parse::<GroupBy>(), Ok(GroupBy::Month));
+        assert_eq!("quarter".parse::<GroupBy>(), Ok(GroupBy::Quarter));
+        assert_eq!("year".parse::<GroupBy>(), Ok(GroupBy::Year));
+        assert_eq!("rolling-quarter".parse::<GroupBy>(), Ok(GroupBy::RollingQuarter));
+        assert_eq!("week-of-month".parse::<GroupBy>(), Ok(GroupBy::WeekOfMonth));
+    }
+
+    #[test]
+    fn group_by_rejects_unknown_names() {
+        assert_eq!(
+            "monht".parse::<GroupBy>(),
+            Err(GroupByParseError(String::from("monht")))
+        );
+        assert_eq!("".parse::<GroupBy>(), Err(GroupByParseError(String::new())));
+        assert_eq!(
+            GroupByParseError(String::from("monht")).to_string(),
+            "Unknown grouping 'monht'"
+        );
+    }
+
+    #[test]
+    fn by_week_of_month_buckets_days_into_weeks() {
+        let week = |day: u32| by_week_of_month(NaiveDate::from_ymd(2024, 1, day));
+        assert_eq!(week(1), Period::from("2024-01-W1"));
+        assert_eq!(week(7), Period::from("2024-01-W1"));
+        assert_eq!(week(8), Period::from("2024-01-W2"));
+        assert_eq!(week(28), Period::from("2024-01-W4"));
+    }
+
+    #[test]
+    fn by_week_of_month_puts_days_29_to_31_in_fifth_week() {
+        for day in 29..=31 {
+            assert_eq!(
+                by_week_of_month(NaiveDate::from_ymd(2024, 1, day)),
+                Period::from("2024-01-W5")
+            );
+        }
+        assert_eq!(
+            by_week_of_month(NaiveDate::from_ymd(2024, 2, 29)),
+            Period::from("2024-02-W5")
+        );
+    }
+
+    #[test]
+    fn pearson_correlation_of_known_inputs() {
+        let x = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert!((pearson_correlation(&x, &[2.0, 4.0, 6.0, 8.0, 10.0]) - 1.0).abs() < 1e-12);
+        assert!((pearson_correlation(&x, &[5.0, 4.0, 3.0, 2.0, 1.0]) + 1.0).abs() < 1e-12);
+        assert!((pearson_correlation(&x, &[2.0, 1.0, 4.0, 3.0, 5.0]) - 0.8).abs() < 1e-12);
+        assert!(pearson_correlation(&x, &[3.0; 5]).is_nan());
+        assert!(pearson_correlation(&[1.0], &[1.0]).is_nan());
+    }
+
+    #[test]
+    fn category_correlation_is_symmetric() {
+        let months = periods(&["2021-01", "2021-02", "2021-03"]);
+        let data = worksheet(&[
+            ("Бензин", "2021-01", 100.0),
+            ("Бензин", "2021-02", 200.0),
+            ("Бензин", "2021-03", 300.0),
+            ("Платные дороги", "2021-01", 10.0),
+            ("Платные дороги", "2021-02", 20.0),
+            ("Платные дороги", "2021-03", 30.0),
+        ]);
+        let fuel = Category::from("Бензин");
+        let tolls = Category::from("Платные дороги");
+
+        let matrix = category_correlation(&data, &months);
+        assert_eq!(matrix.len(), 4);
+        assert!((matrix[&(fuel.clone(), tolls.clone())] - 1.0).abs() < 1e-12);
+        assert_eq!(matrix[&(fuel.clone(), tolls.clone())], matrix[&(tolls, fuel)]);
+    }
+
+    #[test]
+    fn merge_small_categories_collapses_into_other() {
+        let months = periods(&["2021-01", "2021-02"]);
+        let data = worksheet(&[
+            ("Аренда", "2021-01", 1000.0),
+            ("Аренда", "2021-02", 1000.0),
+            ("Еда", "2021-01", 500.0),
+            ("Еда", "2021-02", 500.0),
+            ("Кофе", "2021-01", 10.0),
+            ("Кофе", "2021-02", 15.0),
+            ("Парковка", "2021-01", 5.0),
+        ]);
+
+        let merged = merge_small_categories(data.clone(), &months, 2.0);
+        assert!(merged.len() < data.len());
+        assert_eq!(
+            merged,
+            worksheet(&[
+                ("Аренда", "2021-01", 1000.0),
+                ("Аренда", "2021-02", 1000.0),
+                ("Еда", "2021-01", 500.0),
+                ("Еда", "2021-02", 500.0),
+                ("Other", "2021-01", 15.0),
+                ("Other", "2021-02", 15.0),
+            ])
+        );
+    }
+
+    fn fortnight(date: NaiveDate) -> Period {
+        let half = if date.day() < 15 { 1 } else { 2 };
+        Period::from(format!("{:04}-{:02}-F{}", date.year(), date.month(), half))
+    }
+
+    #[test]
+    fn by_custom_fn_applies_fortnightly_grouper() {
+        let period = |day: u32| by_custom_fn(NaiveDate::from_ymd(2021, 3, day), fortnight);
+        assert_eq!(period(1), Period::from("2021-03-F1"));
+        assert_eq!(period(14), Period::from("2021-03-F1"));
+        assert_eq!(period(15), Period::from("2021-03-F2"));
+        assert_eq!(period(31), Period::from("2021-03-F2"));
+    }
+
+    #[test]
+    fn custom_group_by_aggregates_into_fortnights() {
+        let group_by = period_from_date(GroupBy::Custom(Arc::new(fortnight)));
+        let rows = [
+            header_row(),
+            tx_row("01.03.2021", "Еда", "Расход", 100.0),
+            tx_row("14.03.2021", "Еда", "Расход", 20.0),
+            tx_row("15.03.2021", "Еда", "Расход", 5.0),
+            tx_row("31.03.2021", "Еда", "Расход", 1.0),
+        ];
+        let data = read_worksheet(
+            String::from("Лист1"),
+            range(&rows),
+            &*group_by,
+            &ParseOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            data,
+            worksheet(&[("Еда", "2021-03-F1", 120.0), ("Еда", "2021-03-F2", 6.0)])
+        );
+    }
+
+    #[test]
+    fn infer_group_by_from_data_detects_each_pattern() {
+        let infer = |names: &[&str]| infer_group_by_from_data(&periods(names));
+        assert_eq!(infer(&["2021-01", "2021-02"]), GroupBy::Month);
+        assert_eq!(infer(&["2021-q1", "2021-q2"]), GroupBy::Quarter);
+        assert_eq!(infer(&["2020", "2021"]), GroupBy::Year);
+        assert_eq!(infer(&["2020-11..01", "2020-12..02"]), GroupBy::RollingQuarter);
+        assert_eq!(infer(&["2021-01-W1", "2021-01-W5"]), GroupBy::WeekOfMonth);
+    }
+
+    #[test]
+    fn infer_group_by_from_data_falls_back_to_month() {
+        assert_eq!(infer_group_by_from_data(&periods(&["весна", "лето"])), GroupBy::Month);
+        assert_eq!(infer_group_by_from_data(&[]), GroupBy::Month);
+    }
+
+    #[test]
+    fn infer_group_by_from_dates_uses_recorded_granularity() {
+        let dates = |dates: &[(i32, u32, u32)]| -> Vec<NaiveDate> {
+            dates
+                .iter()
+                .map(|(y, m, d)| NaiveDate::from_ymd(*y, *m, *d))
+                .collect()
+        };
+        assert_eq!(
+            infer_group_by_from_dates(&dates(&[(2020, 1, 1), (2021, 1, 1)])),
+            Some(GroupBy::Year)
+        );
+        assert_eq!(
+            infer_group_by_from_dates(&dates(&[(2021, 1, 1), (2021, 4, 1), (2021, 10, 1)])),
+            Some(GroupBy::Quarter)
+        );
+        assert_eq!(
+            infer_group_by_from_dates(&dates(&[(2021, 1, 1), (2021, 2, 1)])),
+            Some(GroupBy::Month)
+        );
+        assert_eq!(
+            infer_group_by_from_dates(&dates(&[(2021, 1, 15), (2021, 2, 3)])),
+            Some(GroupBy::Month)
+        );
+        assert_eq!(infer_group_by_from_dates(&[]), None);
+    }
+
+    #[test]
+    fn format_chart_title_substitutes_sheet_name() {
+        assert_eq!(format_chart_title("{sheet} expenses", "Карта"), "Карта expenses");
+        assert_eq!(format_chart_title("{sheet} / {sheet}", "Наличные"), "Наличные / Наличные");
+        assert_eq!(format_chart_title("Все траты", "Карта"), "Все траты");
+    }
+
+    #[test]
+    fn moving_median_removes_spike_that_moving_average_keeps() {
+        let values = [10.0, 10.0, 10.0, 100.0, 10.0, 10.0, 10.0];
+
+        assert_eq!(moving_median(&values, 3), vec![10.0; 7]);
+
+        let averaged = moving_average(&values, 3);
+        assert_eq!(averaged.len(), values.len());
+        assert!(averaged[3..6].iter().all(|v| (*v - 40.0).abs() < 1e-9));
+    }
+
+    #[cfg(feature = "streaming")]
+    #[test]
+    fn read_worksheet_streaming_matches_in_memory_reader() {
+        let path = std::env::temp_dir().join(format!("{}.xlsx", Uuid::new_v4()));
+        let headers = ColumnHeaders::default();
+        let labels = TxTypeLabels::default();
+        {
+            let workbook = Workbook::new(path.to_str().unwrap());
+            let date_format = workbook.add_format().set_num_format("dd.mm.yyyy");
+            let mut sheet = workbook.add_worksheet(Some("Счёт")).unwrap();
+            let columns = [&headers.period, &headers.category, &headers.tx_type, &headers.value];
+            for (col, name) in columns.iter().enumerate() {
+                sheet.write_string(0, col as u16, name, None).unwrap();
+            }
+            sheet.write_string(1, 0, "05.01.2021", None).unwrap();
+            sheet.write_string(1, 1, "Еда", None).unwrap();
+            sheet.write_string(1, 2, &labels.outcome, None).unwrap();
+            sheet.write_number(1, 3, 100.0, None).unwrap();
+            // 2021-01-20 as an Excel serial date
+            sheet.write_number(2, 0, 44216.0, Some(&date_format)).unwrap();
+            sheet.write_string(2, 1, "Еда", None).unwrap();
+            sheet.write_string(2, 2, &labels.income, None).unwrap();
+            sheet.write_number(2, 3, 30.0, None).unwrap();
+            sheet.write_string(3, 0, "01.02.2021", None).unwrap();
+            sheet.write_string(3, 1, "Еда", None).unwrap();
+            sheet.write_string(3, 2, &labels.transfer, None).unwrap();
+            sheet.write_number(3, 3, 500.0, None).unwrap();
+            sheet.write_string(4, 0, "02.02.2021", None).unwrap();
+            sheet.write_string(4, 1, "Транспорт", None).unwrap();
+            workbook.close().unwrap();
+        }
+
+        let streamed = read_worksheet_streaming(&path, "Счёт", by_month).unwrap();
+        let mut workbook: Xlsx<_> = open_workbook(&path).unwrap();
+        let range = workbook.worksheet_range("Счёт").unwrap().unwrap();
+        let in_memory =
+            read_worksheet_from_range(String::from("Счёт"), range, by_month, headers).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(streamed, in_memory);
+        assert_eq!(streamed.len(), 1);
+        assert_eq!(streamed["Еда"][&Period::from("2021-01")], 70.0);
+        assert!(matches!(
+            read_worksheet_streaming(Path::new("missing.xlsx"), "Счёт", by_month),
+            Err(MyCustomError::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn export_plain_text_report_writes_header_totals_and_averages() {
+        let data = worksheet(&[
+            ("Еда", "2021-01", 1000.0),
+            ("Еда", "2021-02", 3000.0),
+            ("Транспорт", "2021-01", 500.0),
+        ]);
+        let periods = periods(&["2021-01", "2021-02"]);
+
+        let mut output = Vec::new();
+        export_plain_text_report(&data, &periods, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+
+        let row = |cells: &[&str]| {
+            let mut line = format!("{:<9}", cells[0]);
+            for cell in &cells[1..] {
+                line.push_str(&format!(" {:>15}", cell));
+            }
+            line
+        };
+        assert_eq!(lines[0], row(&["Категория", "2021-01", "2021-02", "Среднее"]));
+        assert_eq!(lines[1], "-".repeat(9 + 3 * 16));
+        assert_eq!(lines[2], row(&["Еда", "1,000.00", "3,000.00", "2,000.00"]));
+        assert_eq!(lines[3], row(&["Транспорт", "500.00", "0.00", "250.00"]));
+        assert_eq!(lines[5], row(&["Всего", "1,500.00", "3,000.00", "2,250.00"]));
+        assert_eq!(lines[6], row(&["Среднее", "750.00", "1,500.00"]));
+    }
+
+    #[test]
+    fn compute_gini_coefficient_is_zero_for_equal_spending() {
+        assert!(compute_gini_coefficient(&[250.0; 8]).abs() < 1e-9);
+        assert_eq!(compute_gini_coefficient(&[]), 0.0);
+        assert_eq!(compute_gini_coefficient(&[0.0, 0.0]), 0.0);
+    }
+
+    #[test]
+    fn compute_gini_coefficient_approaches_one_for_single_category() {
+        let mut values = vec![0.0; 99];
+        values.push(10_000.0);
+
+        let gini = compute_gini_coefficient(&values);
+        assert!((gini - 0.99).abs() < 1e-9);
+        assert!((compute_gini_coefficient(&[0.0, 0.0, 0.0, 40.0]) - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn lorenz_curve_runs_from_origin_to_one_in_ascending_order() {
+        let curve = lorenz_curve(&[300.0, 100.0]);
+        assert_eq!(curve, vec![(0.0, 0.0), (0.5, 0.25), (1.0, 1.0)]);
+        assert!(lorenz_curve(&[]).is_empty());
+    }
+
+    #[test]
+    fn apply_color_mode_dark_serializes_non_white_background() {
+        let dark = serde_json::to_value(apply_color_mode(Layout::new(), ColorMode::Dark)).unwrap();
+        assert_eq!(dark["paper_bgcolor"], "rgb(17, 17, 17)");
+        assert_eq!(dark["plot_bgcolor"], "rgb(17, 17, 17)");
+        assert_eq!(dark["font"]["color"], "rgb(230, 230, 230)");
+
+        let light = serde_json::to_value(apply_color_mode(Layout::new(), ColorMode::Light)).unwrap();
+        assert!(light.get("paper_bgcolor").is_none());
+    }
+
+    #[test]
+    fn palette_for_color_mode_replaces_black_in_dark_mode() {
+        let light = palette_for_color_mode(Palette::Colorblind, ColorMode::Light);
+        let dark = palette_for_color_mode(Palette::Colorblind, ColorMode::Dark);
+
+        assert!(light.contains(&[0, 0, 0]));
+        assert!(!dark.contains(&[0, 0, 0]));
+        assert!(dark.contains(&DARK_MODE_FOREGROUND));
+        assert_eq!(light.len(), dark.len());
+    }
+
+    #[test]
+    fn read_row_lenient_flags_defaulted_period() {
+        let defaults = RowDefaults::default();
+
+        let fields =
+            read_row_lenient(&columns(), &row(string_cell("15.03.2021"), DataType::Empty), &defaults);
+        assert!(!fields.period_defaulted);
+        assert_eq!(fields.value, 0.0);
+
+        let fields =
+            read_row_lenient(&columns(), &row(string_cell("не дата"), DataType::Float(10.0)), &defaults);
+        assert!(fields.period_defaulted);
+        assert_eq!(fields.period, defaults.period);
+        assert_eq!(fields.value, 10.0);
+    }
+
+    #[test]
+    fn lenient_parsing_skips_empty_rows_and_keeps_rows_without_period() {
+        let options = ParseOptions {
+            lenient: true,
+            ..ParseOptions::default()
+        };
+        let rows = [
+            header_row(),
+            tx_row("15.03.2021", "Еда", "Расход", 100.0),
+            vec![DataType::Empty, string_cell(" "), DataType::Empty, DataType::Empty],
+            tx_row("", "Еда", "Расход", 50.0),
+            vec![string_cell("16.03.2021"), string_cell("Еда"), DataType::Empty, DataType::Empty],
+        ];
+
+        let (fields, _currency) = read_worksheet_fields("Лист1", &range(&rows), &options).unwrap();
+
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields[0].value, 100.0);
+        assert!(!fields[0].period_defaulted);
+        assert_eq!(fields[1].period, RowDefaults::default().period);
+        assert_eq!(fields[1].value, 50.0);
+        assert!(fields[1].period_defaulted);
+        assert_eq!(fields[2].period, NaiveDate::from_ymd(2021, 3, 16));
+        assert_eq!(fields[2].tx_type, TxType::Outcome);
+        assert_eq!(fields[2].value, 0.0);
+        assert!(!fields[2].period_defaulted);
+    }
+
+    #[test]
+    fn export_html_table_prints_dash_for_missing_delta() {
+        let data = worksheet(&[("Еда", "2021-01", 0.0), ("Еда", "2021-02", 100.0)]);
+        let periods = periods(&["2021-01", "2021-02"]);
+        let delta = compute_period_over_period_delta(&data, &periods);
+
+        let mut output = Vec::new();
+        export_html_table(&delta, &periods[1..], &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.contains("<td class=\"value\">—</td>"));
+        assert!(!output.contains("NaN"));
+    }
+
+    #[test]
+    fn pie_trace_serializes_as_plotly_pie() {
+        let trace = PieTrace::new(vec![String::from("Еда")], vec![100.0]).name("Всего");
+        let json: serde_json::Value = serde_json::from_str(&Trace::serialize(&*trace)).unwrap();
+
+        assert_eq!(json["type"], "pie");
+        assert_eq!(json["name"], "Всего");
+        assert_eq!(json["labels"][0], "Еда");
+        assert_eq!(json["values"][0], 100.0);
+    }
+
+    #[test]
+    fn generate_full_report_inlines_plotly_and_names_sheets() {
+        let data = vec![worksheet(&[
+            ("Еда", "2021-01", 0.0),
+            ("Еда", "2021-02", 100.0),
+            ("Транспорт", "2021-01", 50.0),
+            ("Транспорт", "2021-02", 25.0),
+        ])];
+        let config = ReportConfig {
+            sheet_names: vec![String::from("Наличные")],
+            ..ReportConfig::default()
+        };
+        let path = std::env::temp_dir().join(format!("{}.html", Uuid::new_v4()));
+
+        generate_full_report(data, &config, &path).unwrap();
+        let html = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(html.contains("<h1>Наличные</h1>"));
+        assert!(!html.contains("cdn.plot.ly"));
+        assert_eq!(html.matches(&plotly_script().unwrap()).count(), 1);
+        assert!(html.contains("\"type\":\"pie\""));
+        assert!(html.contains("<td class=\"value\">—</td>"));
+    }
+
+    fn variance_record(category: &str, period: &str, budget: f64, actual: f64) -> BudgetVarianceRecord {
+        BudgetVarianceRecord {
+            category: Category::from(category),
+            period: Period::from(period),
+            actual,
+            budget,
+            variance: actual - budget,
+            pct_over_budget: if budget == 0.0 { 0.0 } else { (actual - budget) / budget * 100.0 },
+        }
+    }
+
+    #[test]
+    fn compute_budget_adherence_score_edge_cases() {
+        assert_eq!(compute_budget_adherence_score(&[]), 100.0);
+        assert_eq!(
+            compute_budget_adherence_score(&[variance_record("Еда", "2021-01", 0.0, 500.0)]),
+            0.0
+        );
+        assert_eq!(
+            compute_budget_adherence_score(&[variance_record("Еда", "2021-01", 500.0, 0.0)]),
+            100.0
+        );
+        let over = compute_budget_adherence_score(&[variance_record("Еда", "2021-01", 100.0, 300.0)]);
+        assert!((over - 100.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn compute_budget_adherence_score_weights_categories_equally() {
+        let records = [
+            variance_record("Еда", "2021-01", 100.0, 150.0),
+            variance_record("Еда", "2021-02", 100.0, 50.0),
+            variance_record("Еда", "2021-03", 100.0, 100.0),
+            variance_record("Транспорт", "2021-01", 100.0, 200.0),
+        ];
+
+        // Еда is on budget overall (300 of 300), Транспорт is at 50%.
+        assert_eq!(compute_budget_adherence_score(&records), 75.0);
+    }
+
+    #[test]
+    fn tx_type_label_uses_configured_labels() {
+        let labels = TxTypeLabels::default();
+        assert_eq!(TxType::Income.label(&labels), "Доход");
+        assert_eq!(TxType::Transfer.label(&labels), "Перевод");
+        assert_eq!(TxType::Income.to_string(), "income");
+        assert_eq!(format!("{:#}", TxType::Outcome), "outcome");
+
+        let labels = TxTypeLabels {
+            income: String::from("Income"),
+            ..TxTypeLabels::default()
+        };
+        assert_eq!(TxType::Income.label(&labels), "Income");
+    }
+
+    #[test]
+    fn rolling_quarter_sums_overlap_three_months() {
+        let monthly = worksheet(&[
+            ("Еда", "2020-12", 10.0),
+            ("Еда", "2021-01", 20.0),
+            ("Еда", "2021-02", 40.0),
+            ("Транспорт", "2021-03", 5.0),
+        ]);
+
+        let rolling = rolling_quarter_sums(monthly);
+
+        assert_eq!(
+            rolling,
+            worksheet(&[
+                ("Еда", "2020-10..12", 10.0),
+                ("Еда", "2020-11..01", 30.0),
+                ("Еда", "2020-12..02", 70.0),
+                ("Еда", "2021-01..03", 60.0),
+                ("Транспорт", "2021-01..03", 5.0),
+            ])
+        );
+    }
+
+    #[test]
+    fn rolling_quarter_periods_advance_by_one_month() {
+        let periods = periods(&["2020-11..01", "2020-12..02", "2021-02..04"]);
+        let missing = validate_period_continuity(&periods, GroupBy::RollingQuarter);
+
+        assert_eq!(
+            missing,
+            vec![MissingPeriod {
+                after: Period::from("2020-12..02"),
+                expected: Period::from("2021-01..03"),
+            }]
+        );
+        assert_eq!(
+            infer_date_range_from_data(&periods, &GroupBy::RollingQuarter, 3),
+            (Period::from("2020-12..02"), Period::from("2021-02..04"))
+        );
+    }
+
+    #[test]
+    fn compute_spending_velocity_uses_calendar_days() {
+        let velocity = |entries: &[(&str, &str, f64)], names: &[&str], group_by: GroupBy| {
+            compute_spending_velocity(&worksheet(entries), &periods(names), group_by)["Еда"]
+        };
+
+        assert_eq!(velocity(&[("Еда", "2021-q1", 900.0)], &["2021-q1"], GroupBy::Quarter), 10.0);
+        assert_eq!(velocity(&[("Еда", "2020", 732.0)], &["2020"], GroupBy::Year), 2.0);
+        assert_eq!(velocity(&[("Еда", "2021", 730.0)], &["2021"], GroupBy::Year), 2.0);
+        // December through February: 31 + 31 + 28 days.
+        assert_eq!(
+            velocity(&[("Еда", "2020-12..02", 180.0)], &["2020-12..02"], GroupBy::RollingQuarter),
+            2.0
+        );
+        assert_eq!(
+            velocity(
+                &[("Еда", "2021-01", 310.0), ("Еда", "2021-02", 560.0)],
+                &["2021-01", "2021-02"],
+                GroupBy::Month
+            ),
+            15.0
+        );
+    }
+
+    #[test]
+    fn write_gnuplot_script_uses_resolved_chart_title() {
+        let config = DrawConfig {
+            chart_title: Some(String::from("Траты \"{sheet}\"")),
+            sheet_names: vec![String::from("Карта")],
+            ..DrawConfig::default()
+        };
+        let data = worksheet(&[("Еда", "2021-01", 100.0)]);
+
+        let mut script = Vec::new();
+        write_gnuplot_script(&data, &chart_title(&config, 0), "data.dat", "chart.svg", &config, &mut script)
+            .unwrap();
+        let script = String::from_utf8(script).unwrap();
+
+        assert!(script.contains("set title \"Траты 'Карта'\"\n"));
+        assert_eq!(chart_title(&DrawConfig::default(), 0), "Все траты");
+    }
+
+    #[test]
+    fn plot_keeps_net_worth_axis_apart_from_percent_axis() {
+        let data = worksheet(&[
+            ("Еда", "2021-01", 100.0),
+            ("Еда", "2021-02", 120.0),
+            ("Зарплата", "2021-01", -500.0),
+            ("Зарплата", "2021-02", -500.0),
+        ]);
+        let periods = periods(&["2021-01", "2021-02"]);
+        let html = |show_savings_rate: bool| {
+            let config = DrawConfig {
+                show_net_worth: true,
+                show_savings_rate,
+                ..DrawConfig::default()
+            };
+            plot(String::from("Все траты"), &data, &periods, &config)
+                .unwrap()
+                .to_inline_html(Some("chart"))
+        };
+
+        let both = html(true);
+        assert!(both.contains("\"yaxis3\""));
+        assert!(both.contains("\"anchor\":\"free\""));
+        assert!(both.contains(&format!("\"domain\":[0.0,{:?}]", THIRD_AXIS_X_DOMAIN_END)));
+
+        let net_worth_only = html(false);
+        assert!(!net_worth_only.contains("\"yaxis3\""));
+        assert!(net_worth_only.contains("\"yaxis2\""));
+    }
+
+    #[test]
+    fn read_transaction_list_composes_into_read_worksheet() {
+        let path = std::env::temp_dir().join(format!("{}.xlsx", Uuid::new_v4()));
+        let options = ParseOptions {
+            column_headers: ColumnHeaders {
+                period: String::from("Дата"),
+                ..ColumnHeaders::default()
+            },
+            tx_type_labels: TxTypeLabels {
+                income: String::from("Приход"),
+                ..TxTypeLabels::default()
+            },
+            skip_rows: 1,
+            sheet_filter: Some(Regex::new("^Карта$").unwrap()),
+            ..ParseOptions::default()
+        };
+        {
+            let workbook = Workbook::new(path.to_str().unwrap());
+            for name in &["Карта", "Наличные"] {
+                let mut sheet = workbook.add_worksheet(Some(*name)).unwrap();
+                sheet.write_string(0, 0, "Выписка", None).unwrap();
+                let headers = ["Дата", "Категория", "Доход/Расход", "RUB"];
+                for (col, header) in headers.iter().enumerate() {
+                    sheet.write_string(1, col as u16, header, None).unwrap();
+                }
+                let rows = [
+                    ("05.01.2021", "Еда", "Расход", 100.0),
+                    ("20.01.2021", "Еда", "Приход", 30.0),
+                    ("01.02.2021", "Еда", "Перевод", 500.0),
+                    ("03.02.2021", "Связь", "Расход", 15.0),
+                ];
+                for (i, (date, category, tx_type, value)) in rows.iter().enumerate() {
+                    let row = i as u32 + 2;
+                    sheet.write_string(row, 0, date, None).unwrap();
+                    sheet.write_string(row, 1, category, None).unwrap();
+                    sheet.write_string(row, 2, tx_type, None).unwrap();
+                    sheet.write_number(row, 3, *value, None).unwrap();
+                }
+            }
+            workbook.close().unwrap();
+        }
+
+        let file = path.to_str().unwrap().to_string();
+        let fields = read_transaction_list(file.clone(), &options).unwrap();
+        let mut workbook: Xlsx<_> = open_workbook(&path).unwrap();
+        let range = workbook.worksheet_range("Карта").unwrap().unwrap();
+        let expected = read_worksheet(String::from("Карта"), range, &by_month, &options).unwrap();
+        let report = parse_report(file, GroupBy::Month, &options).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields_to_worksheet_data(&fields, by_month), expected);
+        assert_eq!(report, vec![expected]);
     }
 }
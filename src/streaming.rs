@@ -0,0 +1,356 @@
+use crate::MyCustomError;
+use calamine::DataType;
+use chrono::NaiveDate;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek};
+use std::path::Path;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+const BUILTIN_DATE_FORMATS: &[u32] = &[14, 15, 16, 17, 18, 19, 20, 21, 22, 45, 46, 47];
+
+fn format_error(e: impl std::fmt::Display) -> MyCustomError {
+    MyCustomError::XlsxFormatError(e.to_string())
+}
+
+fn xml_reader<R: Read>(reader: R) -> Reader<BufReader<R>> {
+    let mut reader = Reader::from_reader(BufReader::new(reader));
+    reader.trim_text(false);
+    reader
+}
+
+fn local_name(key: &[u8]) -> &[u8] {
+    key.rsplit(|b| *b == b':').next().unwrap_or(key)
+}
+
+fn attribute<R: BufRead>(
+    reader: &Reader<R>,
+    e: &BytesStart,
+    key: &[u8],
+) -> Result<Option<String>, MyCustomError> {
+    for attr in e.attributes() {
+        let attr = attr.map_err(format_error)?;
+        if local_name(attr.key) == key {
+            let value = attr
+                .unescape_and_decode_value(reader)
+                .map_err(format_error)?;
+            return Ok(Some(value));
+        }
+    }
+    Ok(None)
+}
+
+fn is_date_format(code: &str) -> bool {
+    let mut escaped = false;
+    let mut quoted = false;
+    let mut bracketed = false;
+
+    for c in code.chars() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' | '_' | '*' if !quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '[' if !quoted => bracketed = true,
+            ']' if !quoted => bracketed = false,
+            _ if quoted || bracketed => {}
+            'd' | 'm' | 'y' | 'h' | 's' | 'D' | 'M' | 'Y' | 'H' | 'S' => return true,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+fn read_shared_strings<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+) -> Result<Vec<String>, MyCustomError> {
+    let file = match archive.by_name("xl/sharedStrings.xml") {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(Vec::new()),
+        Err(e) => return Err(format_error(e)),
+    };
+
+    let mut reader = xml_reader(file);
+    let mut buf = Vec::new();
+    let mut strings = Vec::new();
+    let mut current = String::new();
+    let mut in_text = false;
+    let mut in_phonetic = false;
+
+    loop {
+        match reader.read_event(&mut buf).map_err(format_error)? {
+            Event::Start(e) => match e.local_name() {
+                b"si" => current.clear(),
+                b"t" => in_text = !in_phonetic,
+                b"rPh" => in_phonetic = true,
+                _ => {}
+            },
+            Event::Text(e) if in_text => {
+                current.push_str(&e.unescape_and_decode(&reader).map_err(format_error)?)
+            }
+            Event::End(e) => match e.local_name() {
+                b"si" => strings.push(std::mem::take(&mut current)),
+                b"t" => in_text = false,
+                b"rPh" => in_phonetic = false,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(strings)
+}
+
+fn read_date_styles<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+) -> Result<Vec<bool>, MyCustomError> {
+    let file = match archive.by_name("xl/styles.xml") {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(Vec::new()),
+        Err(e) => return Err(format_error(e)),
+    };
+
+    let mut reader = xml_reader(file);
+    let mut buf = Vec::new();
+    let mut custom_formats = HashMap::new();
+    let mut styles = Vec::new();
+    let mut in_cell_xfs = false;
+
+    loop {
+        match reader.read_event(&mut buf).map_err(format_error)? {
+            Event::Start(e) | Event::Empty(e) => match e.local_name() {
+                b"numFmt" => {
+                    let id =
+                        attribute(&reader, &e, b"numFmtId")?.and_then(|id| id.parse::<u32>().ok());
+                    let code = attribute(&reader, &e, b"formatCode")?.unwrap_or_default();
+                    if let Some(id) = id {
+                        custom_formats.insert(id, is_date_format(&code));
+                    }
+                }
+                b"cellXfs" => in_cell_xfs = true,
+                b"xf" if in_cell_xfs => {
+                    let id = attribute(&reader, &e, b"numFmtId")?
+                        .and_then(|id| id.parse::<u32>().ok())
+                        .unwrap_or(0);
+                    let is_date = custom_formats
+                        .get(&id)
+                        .copied()
+                        .unwrap_or_else(|| BUILTIN_DATE_FORMATS.contains(&id));
+                    styles.push(is_date);
+                }
+                _ => {}
+            },
+            Event::End(e) if e.local_name() == b"cellXfs" => in_cell_xfs = false,
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(styles)
+}
+
+fn find_sheet_path<R: Read + Seek>(
+    archive: &mut ZipArchive<R>,
+    sheet_name: &str,
+) -> Result<Option<String>, MyCustomError> {
+    let mut rel_id = None;
+    {
+        let mut reader = xml_reader(archive.by_name("xl/workbook.xml").map_err(format_error)?);
+        let mut buf = Vec::new();
+        loop {
+            match reader.read_event(&mut buf).map_err(format_error)? {
+                Event::Start(e) | Event::Empty(e) if e.local_name() == b"sheet" => {
+                    if attribute(&reader, &e, b"name")?.as_deref() == Some(sheet_name) {
+                        rel_id = attribute(&reader, &e, b"id")?;
+                        break;
+                    }
+                }
+                Event::Eof => break,
+                _ => {}
+            }
+            buf.clear();
+        }
+    }
+
+    let rel_id = match rel_id {
+        Some(rel_id) => rel_id,
+        None => return Ok(None),
+    };
+
+    let mut reader = xml_reader(
+        archive
+            .by_name("xl/_rels/workbook.xml.rels")
+            .map_err(format_error)?,
+    );
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event(&mut buf).map_err(format_error)? {
+            Event::Start(e) | Event::Empty(e) if e.local_name() == b"Relationship" => {
+                if attribute(&reader, &e, b"Id")?.as_deref() == Some(rel_id.as_str()) {
+                    return Ok(attribute(&reader, &e, b"Target")?.map(|target| {
+                        match target.strip_prefix('/') {
+                            Some(absolute) => absolute.to_string(),
+                            None => format!("xl/{}", target),
+                        }
+                    }));
+                }
+            }
+            Event::Eof => return Ok(None),
+            _ => {}
+        }
+        buf.clear();
+    }
+}
+
+fn column_index(reference: &str) -> Option<usize> {
+    let letters = reference
+        .bytes()
+        .take_while(|b| b.is_ascii_alphabetic())
+        .map(|b| (b.to_ascii_uppercase() - b'A') as usize + 1)
+        .fold(0, |index, digit| index * 26 + digit);
+    letters.checked_sub(1)
+}
+
+#[derive(Default)]
+struct Cell {
+    column: usize,
+    cell_type: String,
+    style: usize,
+    value: String,
+    in_value: bool,
+}
+
+impl Cell {
+    fn start<R: BufRead>(
+        reader: &Reader<R>,
+        e: &BytesStart,
+        next_column: usize,
+    ) -> Result<Cell, MyCustomError> {
+        let column = attribute(reader, e, b"r")?
+            .and_then(|reference| column_index(&reference))
+            .unwrap_or(next_column);
+        let cell_type = attribute(reader, e, b"t")?.unwrap_or_default();
+        let style = attribute(reader, e, b"s")?
+            .and_then(|style| style.parse().ok())
+            .unwrap_or(0);
+        Ok(Cell {
+            column,
+            cell_type,
+            style,
+            ..Cell::default()
+        })
+    }
+
+    fn into_data_type(self, shared_strings: &[String], date_styles: &[bool]) -> DataType {
+        if self.value.is_empty() && self.cell_type != "inlineStr" && self.cell_type != "str" {
+            return DataType::Empty;
+        }
+
+        match self.cell_type.as_str() {
+            "s" => self
+                .value
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| shared_strings.get(i))
+                .map(|s| DataType::String(s.clone()))
+                .unwrap_or(DataType::Empty),
+            "inlineStr" | "str" | "e" => DataType::String(self.value),
+            "b" => DataType::Bool(self.value.trim() == "1"),
+            "d" => self
+                .value
+                .get(..10)
+                .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+                .map(|date| {
+                    let days = date - NaiveDate::from_ymd(1899, 12, 30);
+                    DataType::DateTime(days.num_days() as f64)
+                })
+                .unwrap_or(DataType::String(self.value)),
+            _ => match self.value.trim().parse::<f64>().ok() {
+                Some(value) if date_styles.get(self.style).copied().unwrap_or(false) => {
+                    DataType::DateTime(value)
+                }
+                Some(value) => DataType::Float(value),
+                None => DataType::String(self.value),
+            },
+        }
+    }
+}
+
+fn set_cell(row: &mut Vec<DataType>, column: usize, value: DataType) {
+    if row.len() <= column {
+        row.resize(column + 1, DataType::Empty);
+    }
+    row[column] = value;
+}
+
+pub fn for_each_row<F>(path: &Path, sheet_name: &str, mut f: F) -> Result<(), MyCustomError>
+where
+    F: FnMut(usize, Vec<DataType>) -> Result<(), MyCustomError>,
+{
+    let mut archive = ZipArchive::new(File::open(path)?).map_err(format_error)?;
+    let shared_strings = read_shared_strings(&mut archive)?;
+    let date_styles = read_date_styles(&mut archive)?;
+    let sheet_path = find_sheet_path(&mut archive, sheet_name)?
+        .ok_or_else(|| MyCustomError::InvalidConfig(format!("sheet '{}' not found", sheet_name)))?;
+
+    let mut reader = xml_reader(archive.by_name(&sheet_path).map_err(format_error)?);
+    let mut buf = Vec::new();
+    let mut row = Vec::new();
+    let mut row_idx = 0;
+    let mut next_row_idx = 0;
+    let mut cell = Cell::default();
+
+    loop {
+        match reader.read_event(&mut buf).map_err(format_error)? {
+            Event::Start(e) => match e.local_name() {
+                b"row" => {
+                    row_idx = attribute(&reader, &e, b"r")?
+                        .and_then(|r| r.parse::<usize>().ok())
+                        .and_then(|r| r.checked_sub(1))
+                        .unwrap_or(next_row_idx);
+                    next_row_idx = row_idx + 1;
+                    row.clear();
+                }
+                b"c" => cell = Cell::start(&reader, &e, row.len())?,
+                b"v" | b"t" => cell.in_value = true,
+                _ => {}
+            },
+            Event::Empty(e) if e.local_name() == b"row" => {
+                next_row_idx = attribute(&reader, &e, b"r")?
+                    .and_then(|r| r.parse::<usize>().ok())
+                    .unwrap_or(next_row_idx + 1);
+            }
+            Event::Text(e) if cell.in_value => {
+                cell.value
+                    .push_str(&e.unescape_and_decode(&reader).map_err(format_error)?);
+            }
+            Event::End(e) => match e.local_name() {
+                b"v" | b"t" => cell.in_value = false,
+                b"c" => {
+                    let cell = std::mem::take(&mut cell);
+                    let column = cell.column;
+                    set_cell(
+                        &mut row,
+                        column,
+                        cell.into_data_type(&shared_strings, &date_styles),
+                    );
+                }
+                b"row" => f(row_idx, std::mem::take(&mut row))?,
+                b"sheetData" => break,
+                _ => {}
+            },
+            Event::Eof => break,
+            _ => {}
+        }
+        buf.clear();
+    }
+
+    Ok(())
+}
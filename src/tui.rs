@@ -0,0 +1,161 @@
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::execute;
+use crossterm::terminal::{
+    disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
+};
+use crossterm::tty::IsTty;
+use money_manager::{Category, MyCustomError, Period, WorksheetData};
+use ratatui::backend::{Backend, CrosstermBackend};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{
+    Block, Borders, List, ListItem, ListState, Row, Sparkline, Table, TableState,
+};
+use ratatui::{Frame, Terminal};
+use std::io;
+
+struct App<'a> {
+    data: &'a WorksheetData,
+    periods: &'a [Period],
+    categories: Vec<&'a Category>,
+    period_state: ListState,
+    category_state: TableState,
+}
+
+fn step(current: Option<usize>, len: usize, delta: isize) -> Option<usize> {
+    if len == 0 {
+        return None;
+    }
+    let next = current.unwrap_or(0) as isize + delta;
+    Some(next.max(0).min(len as isize - 1) as usize)
+}
+
+impl<'a> App<'a> {
+    fn new(data: &'a WorksheetData, periods: &'a [Period]) -> Self {
+        let mut app = App {
+            data,
+            periods,
+            categories: data.keys().collect(),
+            period_state: ListState::default(),
+            category_state: TableState::default(),
+        };
+        app.period_state.select(periods.len().checked_sub(1));
+        app.category_state.select(step(None, app.categories.len(), 0));
+        app
+    }
+
+    fn select_period(&mut self, delta: isize) {
+        let selected = step(self.period_state.selected(), self.periods.len(), delta);
+        self.period_state.select(selected);
+    }
+
+    fn select_category(&mut self, delta: isize) {
+        let selected = step(self.category_state.selected(), self.categories.len(), delta);
+        self.category_state.select(selected);
+    }
+
+    fn value(&self, cat: &Category, period: &Period) -> f64 {
+        self.data
+            .get(cat)
+            .and_then(|by_cat| by_cat.get(period))
+            .cloned()
+            .unwrap_or(0.0)
+    }
+
+    fn render<B: Backend>(&mut self, f: &mut Frame<B>) {
+        let chunks = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([
+                Constraint::Percentage(20),
+                Constraint::Percentage(40),
+                Constraint::Percentage(40),
+            ])
+            .split(f.size());
+        let highlight = Style::default().add_modifier(Modifier::REVERSED);
+
+        let periods: Vec<ListItem> = self
+            .periods
+            .iter()
+            .map(|period| ListItem::new(period.to_string()))
+            .collect();
+        let periods = List::new(periods)
+            .block(Block::default().title("Периоды").borders(Borders::ALL))
+            .highlight_style(highlight);
+        f.render_stateful_widget(periods, chunks[0], &mut self.period_state);
+
+        let all_periods = self.periods;
+        let selected_period = self.period_state.selected().map(|i| &all_periods[i]);
+        let rows: Vec<Row> = self
+            .categories
+            .iter()
+            .map(|cat| {
+                let value = match selected_period {
+                    Some(period) => format!("{:.2}", self.value(cat, period)),
+                    None => String::new(),
+                };
+                Row::new(vec![cat.to_string(), value])
+            })
+            .collect();
+        let widths = [Constraint::Percentage(70), Constraint::Percentage(30)];
+        let categories = Table::new(rows)
+            .header(Row::new(vec!["Категория", "Сумма"]).style(Style::default().fg(Color::Yellow)))
+            .block(Block::default().title("Категории").borders(Borders::ALL))
+            .widths(&widths)
+            .highlight_style(highlight);
+        f.render_stateful_widget(categories, chunks[1], &mut self.category_state);
+
+        let selected_category = self.category_state.selected().map(|i| self.categories[i]);
+        let (title, values) = match selected_category {
+            Some(cat) => (
+                cat.to_string(),
+                self.periods
+                    .iter()
+                    .map(|period| self.value(cat, period).max(0.0).round() as u64)
+                    .collect(),
+            ),
+            None => (String::new(), Vec::new()),
+        };
+        let sparkline = Sparkline::default()
+            .block(Block::default().title(title).borders(Borders::ALL))
+            .data(&values)
+            .style(Style::default().fg(Color::Green));
+        f.render_widget(sparkline, chunks[2]);
+    }
+}
+
+pub fn is_tty() -> bool {
+    io::stdout().is_tty()
+}
+
+fn event_loop<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> Result<(), MyCustomError> {
+    loop {
+        terminal.draw(|f| app.render(f))?;
+        if let Event::Key(key) = event::read()? {
+            match key.code {
+                KeyCode::Char('q') => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Up => app.select_category(-1),
+                KeyCode::Down => app.select_category(1),
+                KeyCode::Left => app.select_period(-1),
+                KeyCode::Right => app.select_period(1),
+                _ => {}
+            }
+        }
+    }
+}
+
+pub fn run(data: &WorksheetData, periods: &[Period]) -> Result<(), MyCustomError> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let result = event_loop(&mut terminal, App::new(data, periods));
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    result
+}